@@ -7,6 +7,18 @@ import { normalizePath } from "./paths.js";
 export type RendererMode = "image" | "text";
 export type ImageQuality = "balanced" | "high";
 export type VideoFilter = "off" | "ntsc-composite" | "ntsc-svideo" | "ntsc-rgb";
+export type Region = "auto" | "ntsc" | "pal" | "dendy";
+export type PalettePreset = "default" | "fceux" | "nestopia" | "smooth" | "composite-direct";
+export type Upscaler = "off" | "nearest" | "scale2x" | "scale3x" | "hq2x" | "hq3x" | "xbrz";
+
+export const PALETTE_PRESETS: readonly PalettePreset[] = ["default", "fceux", "nestopia", "smooth", "composite-direct"];
+
+export interface Overscan {
+	top: number;
+	bottom: number;
+	left: number;
+	right: number;
+}
 
 export interface NesConfig {
 	romDir: string;
@@ -15,6 +27,18 @@ export interface NesConfig {
 	renderer: RendererMode;
 	imageQuality: ImageQuality;
 	videoFilter: VideoFilter;
+	region: Region;
+	// A preset name or the path of a .pal file
+	palette: string;
+	upscaler: Upscaler;
+	// Used by "nearest" and "xbrz", the other scalers have a fixed factor
+	upscaleFactor: number;
+	// Pixels cropped from each side, NTSC TVs hid about 8 on each
+	overscan: Overscan;
+	// Stretch to the 8:7 pixels of an NTSC TV
+	aspectCorrection: boolean;
+	// Shared memory segment other processes can read frames from, empty to disable
+	sharedFramebuffer: string;
 	pixelScale: number;
 	keybindings: InputMapping;
 }
@@ -32,6 +56,13 @@ export const DEFAULT_CONFIG: NesConfig = {
 	renderer: "image",
 	imageQuality: "balanced",
 	videoFilter: "ntsc-composite",
+	region: "auto",
+	palette: "default",
+	upscaler: "off",
+	upscaleFactor: 2,
+	overscan: { top: 0, bottom: 0, left: 0, right: 0 },
+	aspectCorrection: false,
+	sharedFramebuffer: "",
 	pixelScale: 1.0,
 	keybindings: cloneMapping(DEFAULT_INPUT_MAPPING),
 };
@@ -43,6 +74,13 @@ interface RawConfig {
 	renderer?: unknown;
 	imageQuality?: unknown;
 	videoFilter?: unknown;
+	region?: unknown;
+	palette?: unknown;
+	upscaler?: unknown;
+	upscaleFactor?: unknown;
+	overscan?: unknown;
+	aspectCorrection?: unknown;
+	sharedFramebuffer?: unknown;
 	pixelScale?: unknown;
 	keybindings?: unknown;
 }
@@ -73,6 +111,11 @@ export function normalizeConfig(raw: unknown): NesConfig {
 	const saveDir = resolveConfigPath(normalizePath(saveDirInput, saveDirFallback));
 	const imageQuality = normalizeImageQuality(parsed.imageQuality);
 	const videoFilter = normalizeVideoFilter(parsed.videoFilter);
+	const region = normalizeRegion(parsed.region);
+	const palette = normalizePalette(parsed.palette);
+	const upscaler = normalizeUpscaler(parsed.upscaler);
+	const upscaleFactor = normalizeUpscaleFactor(parsed.upscaleFactor);
+	const overscan = normalizeOverscan(parsed.overscan);
 	const pixelScale = normalizePixelScale(parsed.pixelScale);
 	return {
 		romDir,
@@ -81,6 +124,15 @@ export function normalizeConfig(raw: unknown): NesConfig {
 		renderer: parsed.renderer === "text" ? "text" : DEFAULT_CONFIG.renderer,
 		imageQuality,
 		videoFilter,
+		region,
+		palette,
+		upscaler,
+		upscaleFactor,
+		overscan,
+		aspectCorrection:
+			typeof parsed.aspectCorrection === "boolean" ? parsed.aspectCorrection : DEFAULT_CONFIG.aspectCorrection,
+		sharedFramebuffer:
+			typeof parsed.sharedFramebuffer === "string" ? parsed.sharedFramebuffer.trim() : DEFAULT_CONFIG.sharedFramebuffer,
 		pixelScale,
 		keybindings: normalizeKeybindings(parsed.keybindings),
 	};
@@ -153,6 +205,65 @@ function normalizeVideoFilter(raw: unknown): VideoFilter {
 	}
 }
 
+function normalizeRegion(raw: unknown): Region {
+	switch (raw) {
+		case "ntsc":
+		case "pal":
+		case "dendy":
+			return raw;
+		default:
+			return DEFAULT_CONFIG.region;
+	}
+}
+
+export function isPalettePreset(value: string): value is PalettePreset {
+	return (PALETTE_PRESETS as readonly string[]).includes(value);
+}
+
+function normalizePalette(raw: unknown): string {
+	if (typeof raw !== "string") {
+		return DEFAULT_CONFIG.palette;
+	}
+	if (isPalettePreset(raw)) {
+		return raw;
+	}
+	const palettePath = normalizePath(raw, "");
+	return palettePath ? resolveConfigPath(palettePath) : DEFAULT_CONFIG.palette;
+}
+
+function normalizeUpscaler(raw: unknown): Upscaler {
+	switch (raw) {
+		case "nearest":
+		case "scale2x":
+		case "scale3x":
+		case "hq2x":
+		case "hq3x":
+		case "xbrz":
+			return raw;
+		default:
+			return DEFAULT_CONFIG.upscaler;
+	}
+}
+
+function normalizeUpscaleFactor(raw: unknown): number {
+	if (typeof raw !== "number" || Number.isNaN(raw)) {
+		return DEFAULT_CONFIG.upscaleFactor;
+	}
+	return Math.min(6, Math.max(2, Math.round(raw)));
+}
+
+function normalizeOverscan(raw: unknown): Overscan {
+	const parsed = typeof raw === "object" && raw !== null ? (raw as Record<string, unknown>) : {};
+	const side = (value: unknown): number =>
+		typeof value === "number" && !Number.isNaN(value) ? Math.min(64, Math.max(0, Math.round(value))) : 0;
+	return {
+		top: side(parsed.top),
+		bottom: side(parsed.bottom),
+		left: side(parsed.left),
+		right: side(parsed.right),
+	};
+}
+
 function normalizeKeybindings(raw: unknown): InputMapping {
 	const mapping = cloneMapping(DEFAULT_INPUT_MAPPING);
 	if (!raw || typeof raw !== "object") {
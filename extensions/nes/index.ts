@@ -11,6 +11,7 @@ import {
 	formatConfig,
 	getConfigPath,
 	getDefaultSaveDir,
+	isPalettePreset,
 	loadConfig,
 	normalizeConfig,
 	saveConfig,
@@ -34,7 +35,7 @@ const QUALITY_OPTIONS: Array<{ label: string; value: ImageQuality }> = [
 	{ label: "High — 60 fps", value: "high" },
 ];
 const DISPLAY_FILTER_OPTIONS: Array<{ label: string; value: VideoFilter }> = [
-	{ label: "CRT Classic (default) — authentic scanlines + color bleed", value: "ntsc-composite" },
+	{ label: "CRT Classic (default) — composite color bleed + artifacts", value: "ntsc-composite" },
 	{ label: "CRT Soft — subtle retro look", value: "ntsc-rgb" },
 	{ label: "Sharp — pixel-perfect, no filtering", value: "off" },
 ];
@@ -351,7 +352,11 @@ async function createSession(romPath: string, ctx: ExtensionCommandContext, conf
 
 	let core;
 	try {
-		core = createNesCore({ enableAudio: config.enableAudio, videoFilter: config.videoFilter });
+		core = createNesCore({
+			enableAudio: config.enableAudio,
+			videoFilter: config.videoFilter,
+			region: config.region,
+		});
 	} catch (error) {
 		const message = error instanceof Error ? error.message : String(error);
 		ctx.ui.notify(`Failed to initialize NES core: ${message}`, "error");
@@ -363,12 +368,52 @@ async function createSession(romPath: string, ctx: ExtensionCommandContext, conf
 	}
 	try {
 		core.loadRom(romData);
-	} catch {
+	} catch (error) {
 		core.dispose();
-		ctx.ui.notify(`Failed to load ROM: ${romPath}`, "error");
+		const message = error instanceof Error ? error.message : String(error);
+		ctx.ui.notify(`Failed to load ROM: ${romPath} (${message})`, "error");
 		return null;
 	}
 
+	if (config.palette !== DEFAULT_CONFIG.palette) {
+		try {
+			core.setPalette(
+				isPalettePreset(config.palette) ? config.palette : new Uint8Array(await fs.readFile(config.palette)),
+			);
+		} catch (error) {
+			const message = error instanceof Error ? error.message : String(error);
+			ctx.ui.notify(`Failed to load palette: ${config.palette} (${message})`, "warning");
+		}
+	}
+
+	if (config.upscaler !== DEFAULT_CONFIG.upscaler) {
+		try {
+			core.setScaler(config.upscaler, config.upscaleFactor);
+		} catch (error) {
+			const message = error instanceof Error ? error.message : String(error);
+			ctx.ui.notify(`Failed to enable upscaler: ${config.upscaler} (${message})`, "warning");
+		}
+	}
+
+	const { top, bottom, left, right } = config.overscan;
+	if (config.aspectCorrection || top + bottom + left + right > 0) {
+		try {
+			core.setOverscan(config.overscan, config.aspectCorrection);
+		} catch (error) {
+			const message = error instanceof Error ? error.message : String(error);
+			ctx.ui.notify(`Failed to set overscan (${message})`, "warning");
+		}
+	}
+
+	if (config.sharedFramebuffer) {
+		try {
+			core.setSharedFramebuffer(config.sharedFramebuffer);
+		} catch (error) {
+			const message = error instanceof Error ? error.message : String(error);
+			ctx.ui.notify(`Failed to share framebuffer: ${config.sharedFramebuffer} (${message})`, "warning");
+		}
+	}
+
 	const audioWarning = core.getAudioWarning();
 	if (audioWarning) {
 		ctx.ui.notify(audioWarning, "warning");
@@ -383,6 +428,7 @@ async function createSession(romPath: string, ctx: ExtensionCommandContext, conf
 		core,
 		romPath,
 		saveDir: config.saveDir,
+		frameIntervalMs: 1000 / core.getFrameRate(),
 	});
 	session.start();
 	return session;
@@ -0,0 +1,386 @@
+// Long-lived, named frame segment shared with other local processes.
+// pi_nes_core writes it and the kitty-shm addon reads it.
+//
+// Layout, native endian:
+//   0  magic u32        FRAME_SEGMENT_MAGIC
+//   4  version u32      FRAME_SEGMENT_VERSION
+//   8  format u32       24 RGB or 32 RGBA, as in Kitty's f= key
+//   12 width u32        of the last published frame
+//   16 height u32
+//   20 slot_size u32    data bytes a slot holds
+//   24 sequence u64     seqlock, odd while a frame is being written
+//   32 writer_pid u32   process that created the segment
+//   36 reserved u32
+//   40 two slots of width u32, height u32 and slot_size data bytes
+//
+// Frame n (counting from 1) goes into slot (n - 1) % 2 and the sequence
+// ends at 2n once it's published, so a frame stays intact while the next
+// one is written into the other slot.
+
+use std::ffi::{CStr, CString};
+use std::io;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+use libc::{c_void, close, dev_t, fstat, ftruncate, ino_t, kill, mmap, munmap, pid_t, shm_open, shm_unlink, EPERM, MAP_FAILED, MAP_SHARED, O_CREAT, O_EXCL, O_RDONLY, O_RDWR, PROT_READ, PROT_WRITE};
+
+pub const FRAME_SEGMENT_MAGIC: u32 = u32::from_le_bytes(*b"PNFB");
+pub const FRAME_SEGMENT_VERSION: u32 = 2;
+pub const FORMAT_RGB: u32 = 24;
+pub const FORMAT_RGBA: u32 = 32;
+
+const HEADER_SIZE: usize = 40;
+const SLOT_HEADER_SIZE: usize = 8;
+const SEQUENCE_OFFSET: usize = 24;
+const WRITER_PID_OFFSET: usize = 32;
+// Attempts before a reader gives up on a writer that keeps lapping it
+const READ_RETRIES: usize = 16;
+
+/// Dimensions and number of a frame read from a segment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameInfo {
+	pub width: u32,
+	pub height: u32,
+	pub format: u32,
+	pub frame: u64,
+}
+
+struct Mapping {
+	ptr: *mut u8,
+	size: usize,
+}
+
+impl Mapping {
+	fn open(fd: i32, size: usize, prot: i32) -> io::Result<Self> {
+		let ptr = unsafe { mmap(ptr::null_mut(), size, prot, MAP_SHARED, fd, 0) };
+		if ptr == MAP_FAILED {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(Self { ptr: ptr as *mut u8, size })
+	}
+
+	fn read_u32(&self, offset: usize) -> u32 {
+		unsafe { ptr::read_volatile(self.ptr.add(offset) as *const u32) }
+	}
+
+	fn write_u32(&self, offset: usize, value: u32) {
+		unsafe { ptr::write_volatile(self.ptr.add(offset) as *mut u32, value) }
+	}
+
+	fn sequence(&self) -> &AtomicU64 {
+		unsafe { &*(self.ptr.add(SEQUENCE_OFFSET) as *const AtomicU64) }
+	}
+
+	fn slot_offset(&self, slot: usize, slot_size: usize) -> usize {
+		HEADER_SIZE + slot * (SLOT_HEADER_SIZE + slot_size)
+	}
+}
+
+impl Drop for Mapping {
+	fn drop(&mut self) {
+		unsafe {
+			munmap(self.ptr as *mut c_void, self.size);
+		}
+	}
+}
+
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+fn segment_size(slot_size: usize) -> usize {
+	HEADER_SIZE + 2 * (SLOT_HEADER_SIZE + slot_size)
+}
+
+fn shm_name(name: &str) -> io::Result<CString> {
+	let name = match name.starts_with('/') {
+		true => name.to_string(),
+		false => format!("/{name}"),
+	};
+	CString::new(name).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid shared memory name"))
+}
+
+// Device and inode of an open file, telling segments under the same name apart
+fn file_id(fd: i32) -> io::Result<(dev_t, ino_t)> {
+	let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+	match unsafe { fstat(fd, &mut stat) } {
+		0 => Ok((stat.st_dev, stat.st_ino)),
+		_ => Err(io::Error::last_os_error()),
+	}
+}
+
+fn named_file_id(name: &CStr) -> io::Result<(dev_t, ino_t)> {
+	let fd = unsafe { shm_open(name.as_ptr(), O_RDONLY, 0) };
+	if fd < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	let id = file_id(fd);
+	unsafe {
+		close(fd);
+	}
+	id
+}
+
+fn process_alive(pid: u32) -> bool {
+	let Ok(pid) = pid_t::try_from(pid) else {
+		return false;
+	};
+	// Signal 0 only checks the process exists, EPERM means it belongs to another user
+	pid > 0 && (unsafe { kill(pid, 0) } == 0 || io::Error::last_os_error().raw_os_error() == Some(EPERM))
+}
+
+// A frame segment whose writer has exited without unlinking it. Anything
+// else under the name, including a segment still being set up, is in use.
+fn is_stale(name: &str) -> bool {
+	match FrameSegmentReader::open(name) {
+		Ok(reader) => !process_alive(reader.mapping.read_u32(WRITER_PID_OFFSET)),
+		Err(_) => false,
+	}
+}
+
+/// Writing end of a frame segment. The segment is unlinked when it's
+/// dropped, unless another writer has replaced it since.
+pub struct FrameSegmentWriter {
+	mapping: Mapping,
+	name: CString,
+	file_id: (dev_t, ino_t),
+	slot_size: usize,
+}
+
+impl FrameSegmentWriter {
+	/// Creates the segment `name`. Slots hold frames of up to `slot_size`
+	/// bytes. A frame segment left behind under the same name by a process
+	/// that exited without unlinking it is replaced, anything else there
+	/// fails with `AlreadyExists`.
+	pub fn create(name: &str, format: u32, slot_size: usize) -> io::Result<Self> {
+		let c_name = shm_name(name)?;
+		if slot_size == 0 || slot_size > u32::MAX as usize {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid frame segment slot size"));
+		}
+		let mut fd = unsafe { shm_open(c_name.as_ptr(), O_CREAT | O_EXCL | O_RDWR, 0o600) };
+		if fd < 0 && io::Error::last_os_error().kind() == io::ErrorKind::AlreadyExists {
+			if !is_stale(name) {
+				return Err(io::Error::new(io::ErrorKind::AlreadyExists, "shared memory name is in use"));
+			}
+			// Readers of the old segment keep their mapping, new ones get this one
+			fd = unsafe {
+				shm_unlink(c_name.as_ptr());
+				shm_open(c_name.as_ptr(), O_CREAT | O_EXCL | O_RDWR, 0o600)
+			};
+		}
+		if fd < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let size = segment_size(slot_size);
+		let mapping = match unsafe { ftruncate(fd, size as libc::off_t) } {
+			0 => file_id(fd).and_then(|id| Mapping::open(fd, size, PROT_READ | PROT_WRITE).map(|mapping| (mapping, id))),
+			_ => Err(io::Error::last_os_error()),
+		};
+		unsafe {
+			close(fd);
+		}
+		let (mapping, file_id) = mapping.inspect_err(|_| unsafe {
+			shm_unlink(c_name.as_ptr());
+		})?;
+		mapping.write_u32(4, FRAME_SEGMENT_VERSION);
+		mapping.write_u32(8, format);
+		mapping.write_u32(20, slot_size as u32);
+		mapping.write_u32(WRITER_PID_OFFSET, std::process::id());
+		// Readers check the magic last
+		fence(Ordering::Release);
+		mapping.write_u32(0, FRAME_SEGMENT_MAGIC);
+		Ok(Self {
+			mapping,
+			name: c_name,
+			file_id,
+			slot_size,
+		})
+	}
+
+	/// Publishes the next frame, `data` being `width` x `height` pixels of the segment's format.
+	pub fn write_frame(&mut self, width: u32, height: u32, data: &[u8]) -> io::Result<()> {
+		if data.len() > self.slot_size {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame is larger than the segment's slots"));
+		}
+		let sequence = self.mapping.sequence();
+		let start = sequence.load(Ordering::Relaxed);
+		let slot = (start / 2 % 2) as usize;
+		sequence.store(start + 1, Ordering::Relaxed);
+		fence(Ordering::Release);
+		let offset = self.mapping.slot_offset(slot, self.slot_size);
+		self.mapping.write_u32(offset, width);
+		self.mapping.write_u32(offset + 4, height);
+		unsafe {
+			ptr::copy_nonoverlapping(data.as_ptr(), self.mapping.ptr.add(offset + SLOT_HEADER_SIZE), data.len());
+		}
+		self.mapping.write_u32(12, width);
+		self.mapping.write_u32(16, height);
+		sequence.store(start + 2, Ordering::Release);
+		Ok(())
+	}
+}
+
+impl Drop for FrameSegmentWriter {
+	fn drop(&mut self) {
+		if named_file_id(&self.name).is_ok_and(|id| id == self.file_id) {
+			unsafe {
+				shm_unlink(self.name.as_ptr());
+			}
+		}
+	}
+}
+
+/// Read-only view of a segment another process writes.
+pub struct FrameSegmentReader {
+	mapping: Mapping,
+	format: u32,
+	slot_size: usize,
+}
+
+impl FrameSegmentReader {
+	pub fn open(name: &str) -> io::Result<Self> {
+		let c_name = shm_name(name)?;
+		let fd = unsafe { shm_open(c_name.as_ptr(), O_RDONLY, 0) };
+		if fd < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+		let mapping = match unsafe { fstat(fd, &mut stat) } {
+			0 if (stat.st_size as usize) < HEADER_SIZE => {
+				Err(io::Error::new(io::ErrorKind::InvalidData, "not a frame segment"))
+			}
+			0 => Mapping::open(fd, stat.st_size as usize, PROT_READ),
+			_ => Err(io::Error::last_os_error()),
+		};
+		unsafe {
+			close(fd);
+		}
+		let mapping = mapping?;
+		if mapping.read_u32(0) != FRAME_SEGMENT_MAGIC {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "not a frame segment"));
+		}
+		fence(Ordering::Acquire);
+		if mapping.read_u32(4) != FRAME_SEGMENT_VERSION {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported frame segment version"));
+		}
+		let format = mapping.read_u32(8);
+		let slot_size = mapping.read_u32(20) as usize;
+		if segment_size(slot_size) > mapping.size {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated frame segment"));
+		}
+		Ok(Self {
+			mapping,
+			format,
+			slot_size,
+		})
+	}
+
+	pub fn format(&self) -> u32 {
+		self.format
+	}
+
+	/// Number of frames published so far.
+	pub fn frame(&self) -> u64 {
+		self.mapping.sequence().load(Ordering::Acquire) / 2
+	}
+
+	/// Copies the newest complete frame into `target`, resizing it to fit.
+	/// Returns `None` before the first frame or when the writer kept
+	/// overwriting the slot being read.
+	pub fn read_frame(&self, target: &mut Vec<u8>) -> Option<FrameInfo> {
+		let bytes_per_pixel = (self.format / 8) as usize;
+		let sequence = self.mapping.sequence();
+		for _ in 0..READ_RETRIES {
+			let start = sequence.load(Ordering::Acquire);
+			// While frame n + 1 is being written, frame n in the other slot is intact
+			let frame = start / 2;
+			if frame == 0 {
+				return None;
+			}
+			let offset = self.mapping.slot_offset(((frame - 1) % 2) as usize, self.slot_size);
+			let width = self.mapping.read_u32(offset);
+			let height = self.mapping.read_u32(offset + 4);
+			let len = (width as usize * height as usize * bytes_per_pixel).min(self.slot_size);
+			target.resize(len, 0);
+			unsafe {
+				ptr::copy_nonoverlapping(self.mapping.ptr.add(offset + SLOT_HEADER_SIZE), target.as_mut_ptr(), len);
+			}
+			fence(Ordering::Acquire);
+			// The slot gets rewritten from frame n + 2 on
+			if sequence.load(Ordering::Relaxed) <= frame * 2 + 2 {
+				return Some(FrameInfo {
+					width,
+					height,
+					format: self.format,
+					frame,
+				});
+			}
+		}
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn frames_round_trip() {
+		let name = format!("/pnes-test-{}", std::process::id());
+		let mut writer = FrameSegmentWriter::create(&name, FORMAT_RGB, 4 * 2 * 3).unwrap();
+		let reader = FrameSegmentReader::open(&name).unwrap();
+		let mut frame = Vec::new();
+		assert_eq!(None, reader.read_frame(&mut frame));
+
+		writer.write_frame(2, 2, &[1; 12]).unwrap();
+		writer.write_frame(4, 2, &[2; 24]).unwrap();
+		assert!(writer.write_frame(4, 4, &[0; 48]).is_err());
+		let info = reader.read_frame(&mut frame).unwrap();
+		assert_eq!((4, 2, 2), (info.width, info.height, info.frame));
+		assert_eq!(vec![2; 24], frame);
+
+		writer.write_frame(1, 1, &[3; 3]).unwrap();
+		assert_eq!(3, reader.frame());
+		assert_eq!(1, reader.read_frame(&mut frame).unwrap().width);
+		assert_eq!(vec![3; 3], frame);
+
+		drop(writer);
+		assert!(FrameSegmentReader::open(&name).is_err());
+	}
+
+	#[test]
+	fn replaces_stale_segment() {
+		let name = format!("/pnes-stale-{}", std::process::id());
+		let mut stale = FrameSegmentWriter::create(&name, FORMAT_RGBA, 16).unwrap();
+		stale.write_frame(2, 2, &[1; 16]).unwrap();
+		// Left behind like a process that crashed would
+		let mut child = std::process::Command::new("true").spawn().unwrap();
+		child.wait().unwrap();
+		stale.mapping.write_u32(WRITER_PID_OFFSET, child.id());
+
+		let mut writer = FrameSegmentWriter::create(&name, FORMAT_RGB, 12).unwrap();
+		let reader = FrameSegmentReader::open(&name).unwrap();
+		assert_eq!((FORMAT_RGB, 0), (reader.format(), reader.frame()));
+		writer.write_frame(2, 2, &[5; 12]).unwrap();
+		let mut frame = Vec::new();
+		assert_eq!(2, reader.read_frame(&mut frame).unwrap().width);
+		assert_eq!(vec![5; 12], frame);
+
+		// The replaced writer leaves the new segment alone
+		drop(stale);
+		assert!(FrameSegmentReader::open(&name).is_ok());
+		drop(writer);
+		assert!(FrameSegmentReader::open(&name).is_err());
+	}
+
+	#[test]
+	fn keeps_live_segment() {
+		let name = format!("/pnes-live-{}", std::process::id());
+		let mut writer = FrameSegmentWriter::create(&name, FORMAT_RGB, 12).unwrap();
+		let err = FrameSegmentWriter::create(&name, FORMAT_RGB, 12).err().unwrap();
+		assert_eq!(io::ErrorKind::AlreadyExists, err.kind());
+		writer.write_frame(2, 2, &[7; 12]).unwrap();
+		let mut frame = Vec::new();
+		assert!(FrameSegmentReader::open(&name).unwrap().read_frame(&mut frame).is_some());
+		assert_eq!(vec![7; 12], frame);
+	}
+}
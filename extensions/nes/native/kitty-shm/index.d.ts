@@ -4,9 +4,70 @@ export interface SharedMemoryHandle {
   buffer: Uint8Array;
 }
 
+export interface SharedFrame {
+  width: number;
+  height: number;
+  /** 24 RGB or 32 RGBA */
+  format: number;
+  frame: number;
+  data: Uint8Array;
+}
+
+/** Read-only attachment to a frame segment another process publishes into. */
+export class SharedFrameReader {
+  get format(): number;
+  /** Frames published so far, cheap to poll before `readFrame()` */
+  frame(): number;
+  /** Copy of the newest complete frame, null before the first one */
+  readFrame(): SharedFrame | null;
+}
+
+/** Unset values are left out of the sequence. Reusing `imageId` and `placementId` replaces the previous placement. */
+export interface KittyPlacementOptions {
+  imageId?: number;
+  placementId?: number;
+  columns?: number;
+  rows?: number;
+  zIndex?: number;
+}
+
+export interface KittyImageOptions {
+  /** "s" shared memory, "f" file or "d" direct */
+  transport: string;
+  width: number;
+  height: number;
+  /** 24 RGB or 32 RGBA, 24 by default */
+  format?: number;
+  /** File the pixels are written to, required for "f" */
+  path?: string;
+  /** Sends the pixels zlib compressed (o=z) */
+  compress?: boolean;
+  placement?: KittyPlacementOptions;
+}
+
+export interface KittyImage {
+  sequence: string;
+  /** Segment created for "s", closed with `closeSharedMemory()` once the terminal has read it */
+  name?: string;
+}
+
 export function nativeVersion(): string;
 export function createSharedMemory(size: number): SharedMemoryHandle;
 export function closeSharedMemory(name: string): boolean;
+/** Attaches to the frame segment `name` read-only. */
+export function openSharedMemory(name: string): SharedFrameReader;
+/**
+ * Builds the escape sequence transmitting and displaying `frame`, writing the pixels to a new shared memory
+ * segment or to `path` first for the "s" and "f" transports.
+ */
+export function encodeKittyImage(frame: Uint8Array, options: KittyImageOptions): KittyImage;
+/** Shows an already transmitted image again (a=p) without resending it. */
+export function encodeKittyPlacement(options: KittyPlacementOptions): string;
+/**
+ * Deletes the placements of `imageId`, only `placementId` when given, or every visible placement without an
+ * image. `free` also drops the image data.
+ */
+export function encodeKittyDelete(imageId?: number | undefined | null, placementId?: number | undefined | null, free?: boolean | undefined | null): string;
 
 export const isAvailable: boolean;
 export const loadError: unknown | null;
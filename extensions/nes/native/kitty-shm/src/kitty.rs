@@ -0,0 +1,238 @@
+// Kitty graphics protocol escape sequences (ESC _G keys ; payload ESC \).
+// Refer to https://sw.kovidgoyal.net/kitty/graphics-protocol/
+
+use std::fmt::Write;
+
+use miniz_oxide::deflate::compress_to_vec_zlib;
+
+// Direct payloads are sent in chunks of at most this much base64
+const CHUNK_SIZE: usize = 4096;
+const COMPRESSION_LEVEL: u8 = 1;
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Where the terminal reads the pixels from.
+pub enum Transport<'a> {
+	/// Inline in the escape sequence, t=d
+	Direct(&'a [u8]),
+	/// A file holding `size` bytes of pixels, t=f
+	File { path: &'a str, size: usize },
+	/// A POSIX shared memory segment the terminal unlinks after reading, t=s
+	SharedMemory { name: &'a str, size: usize },
+}
+
+/// Where and how an image is shown. Reusing an image and placement id
+/// replaces the previous placement instead of stacking a new one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Placement {
+	pub image_id: Option<u32>,
+	pub placement_id: Option<u32>,
+	pub columns: Option<u32>,
+	pub rows: Option<u32>,
+	pub z_index: Option<i32>,
+}
+
+/// Pixels being transmitted, `format` 24 RGB or 32 RGBA.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageFormat {
+	pub format: u32,
+	pub width: u32,
+	pub height: u32,
+	/// The data is zlib compressed, o=z
+	pub compressed: bool,
+}
+
+/// zlib compression for `ImageFormat::compressed` payloads.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+	compress_to_vec_zlib(data, COMPRESSION_LEVEL)
+}
+
+/// Transmits and displays an image (a=T), quietly so the terminal doesn't
+/// answer into the input stream.
+pub fn encode_transmit(transport: &Transport, image: &ImageFormat, placement: &Placement) -> String {
+	let mut keys = format!("a=T,f={}", image.format);
+	let (medium, size) = match transport {
+		Transport::Direct(_) => ("d", None),
+		Transport::File { size, .. } => ("f", Some(*size)),
+		Transport::SharedMemory { size, .. } => ("s", Some(*size)),
+	};
+	let _ = write!(keys, ",t={medium}");
+	if image.compressed {
+		keys.push_str(",o=z");
+	}
+	let _ = write!(keys, ",q=2,s={},v={}", image.width, image.height);
+	if let Some(size) = size {
+		let _ = write!(keys, ",S={size}");
+	}
+	push_placement_keys(&mut keys, placement);
+	match transport {
+		Transport::Direct(data) => encode_chunked(&keys, data),
+		Transport::File { path, .. } => encode_command(&keys, path.as_bytes()),
+		Transport::SharedMemory { name, .. } => encode_command(&keys, name.as_bytes()),
+	}
+}
+
+/// Displays an image that was already transmitted (a=p).
+pub fn encode_placement(placement: &Placement) -> String {
+	let mut keys = String::from("a=p,q=2");
+	push_placement_keys(&mut keys, placement);
+	encode_command(&keys, &[])
+}
+
+/// Deletes the placements of `image_id`, only `placement_id` when given, or
+/// every visible placement without an image. `free` also releases the image
+/// data so it can't be placed again.
+pub fn encode_delete(image_id: Option<u32>, placement_id: Option<u32>, free: bool) -> String {
+	let target = match (image_id, free) {
+		(Some(_), false) => "i",
+		(Some(_), true) => "I",
+		(None, false) => "a",
+		(None, true) => "A",
+	};
+	let mut keys = format!("a=d,d={target},q=2");
+	if let Some(image_id) = image_id {
+		let _ = write!(keys, ",i={image_id}");
+		if let Some(placement_id) = placement_id {
+			let _ = write!(keys, ",p={placement_id}");
+		}
+	}
+	encode_command(&keys, &[])
+}
+
+fn push_placement_keys(keys: &mut String, placement: &Placement) {
+	let values = [
+		("c", placement.columns.map(i64::from)),
+		("r", placement.rows.map(i64::from)),
+		("i", placement.image_id.map(i64::from)),
+		("p", placement.placement_id.map(i64::from)),
+		("z", placement.z_index.map(i64::from)),
+	];
+	for (key, value) in values {
+		if let Some(value) = value {
+			let _ = write!(keys, ",{key}={value}");
+		}
+	}
+}
+
+fn encode_command(keys: &str, payload: &[u8]) -> String {
+	let mut sequence = String::with_capacity(keys.len() + payload.len() * 4 / 3 + 8);
+	sequence.push_str("\x1b_G");
+	sequence.push_str(keys);
+	if !payload.is_empty() {
+		sequence.push(';');
+		push_base64(&mut sequence, payload);
+	}
+	sequence.push_str("\x1b\\");
+	sequence
+}
+
+// Only the first chunk carries the keys, the rest just m and q
+fn encode_chunked(keys: &str, data: &[u8]) -> String {
+	let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+	push_base64(&mut encoded, data);
+	if encoded.len() <= CHUNK_SIZE {
+		return format!("\x1b_G{keys};{encoded}\x1b\\");
+	}
+	let chunks = encoded.len().div_ceil(CHUNK_SIZE);
+	let mut sequence = String::with_capacity(encoded.len() + keys.len() + chunks * 16);
+	for (index, chunk) in encoded.as_bytes().chunks(CHUNK_SIZE).enumerate() {
+		let more = (index + 1 < chunks) as u8;
+		match index {
+			0 => {
+				let _ = write!(sequence, "\x1b_G{keys},m={more};");
+			}
+			_ => {
+				let _ = write!(sequence, "\x1b_Gm={more},q=2;");
+			}
+		}
+		// Base64 is ASCII, so any split is valid UTF-8
+		sequence.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+		sequence.push_str("\x1b\\");
+	}
+	sequence
+}
+
+fn push_base64(target: &mut String, data: &[u8]) {
+	for chunk in data.chunks(3) {
+		let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+		let bits = ((bytes[0] as u32) << 16) | ((bytes[1] as u32) << 8) | bytes[2] as u32;
+		for i in 0..4 {
+			match i <= chunk.len() {
+				true => target.push(BASE64_ALPHABET[(bits >> (18 - i * 6)) as usize & 0x3F] as char),
+				false => target.push('='),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const RGB_2X1: ImageFormat = ImageFormat {
+		format: 24,
+		width: 2,
+		height: 1,
+		compressed: false,
+	};
+
+	#[test]
+	fn base64() {
+		let mut encoded = String::new();
+		push_base64(&mut encoded, b"pi-nes");
+		push_base64(&mut encoded, b"ab");
+		push_base64(&mut encoded, b"a");
+		assert_eq!("cGktbmVzYWI=YQ==", encoded);
+	}
+
+	#[test]
+	fn transmit_commands() {
+		let placement = Placement {
+			image_id: Some(7),
+			placement_id: Some(1),
+			columns: Some(40),
+			rows: Some(20),
+			z_index: Some(0),
+		};
+		assert_eq!(
+			"\x1b_Ga=T,f=24,t=s,q=2,s=2,v=1,S=6,c=40,r=20,i=7,p=1,z=0;L3BuZXMtMQ==\x1b\\",
+			encode_transmit(&Transport::SharedMemory { name: "/pnes-1", size: 6 }, &RGB_2X1, &placement)
+		);
+		assert_eq!(
+			"\x1b_Ga=T,f=24,t=d,q=2,s=2,v=1;AAAA////\x1b\\",
+			encode_transmit(&Transport::Direct(&[0, 0, 0, 255, 255, 255]), &RGB_2X1, &Placement::default())
+		);
+		assert_eq!("\x1b_Ga=p,q=2,i=7,p=1\x1b\\", encode_placement(&Placement {
+			image_id: Some(7),
+			placement_id: Some(1),
+			..Placement::default()
+		}));
+		assert_eq!("\x1b_Ga=d,d=I,q=2,i=7,p=1\x1b\\", encode_delete(Some(7), Some(1), true));
+		assert_eq!("\x1b_Ga=d,d=a,q=2\x1b\\", encode_delete(None, Some(1), false));
+	}
+
+	#[test]
+	fn chunked_and_compressed() {
+		let image = ImageFormat {
+			format: 24,
+			width: 256,
+			height: 240,
+			compressed: true,
+		};
+		let pixels = vec![0x20; 256 * 240 * 3];
+		let data = compress(&pixels);
+		assert!(data.len() < 1000);
+		assert_eq!(pixels, miniz_oxide::inflate::decompress_to_vec_zlib(&data).unwrap());
+		assert!(encode_transmit(&Transport::Direct(&data), &image, &Placement::default()).contains(",o=z,"));
+
+		let data = (0..5000u32).map(|i| i as u8).collect::<Vec<_>>();
+		let sequence = encode_transmit(&Transport::Direct(&data), &RGB_2X1, &Placement::default());
+		let chunks: Vec<&str> = sequence.split("\x1b\\").filter(|chunk| !chunk.is_empty()).collect();
+		assert_eq!(2, chunks.len());
+		assert!(chunks[0].starts_with("\x1b_Ga=T,f=24,t=d,q=2,s=2,v=1,m=1;"));
+		assert!(chunks[1].starts_with("\x1b_Gm=0,q=2;"));
+		let payload: String = chunks.iter().map(|chunk| &chunk[chunk.find(';').unwrap() + 1..]).collect();
+		let mut expected = String::new();
+		push_base64(&mut expected, &data);
+		assert_eq!(expected, payload);
+	}
+}
@@ -8,6 +8,11 @@ use napi::bindgen_prelude::*;
 use napi_derive::napi;
 use once_cell::sync::Lazy;
 
+pub mod kitty;
+
+use pi_nes_frame_segment::FrameSegmentReader;
+use kitty::{ImageFormat, Placement, Transport};
+
 struct ShmMapping {
 	ptr: *mut u8,
 	size: usize,
@@ -28,6 +33,50 @@ pub struct SharedMemoryHandle {
 	pub buffer: Uint8Array,
 }
 
+#[napi(object)]
+pub struct SharedFrame {
+	pub width: u32,
+	pub height: u32,
+	/// 24 RGB or 32 RGBA
+	pub format: u32,
+	pub frame: i64,
+	pub data: Uint8Array,
+}
+
+/// Read-only attachment to a frame segment another process publishes into.
+#[napi]
+pub struct SharedFrameReader {
+	reader: FrameSegmentReader,
+}
+
+#[napi]
+impl SharedFrameReader {
+	#[napi(getter)]
+	pub fn format(&self) -> u32 {
+		self.reader.format()
+	}
+
+	/// Frames published so far, cheap to poll before `readFrame()`
+	#[napi]
+	pub fn frame(&self) -> i64 {
+		self.reader.frame() as i64
+	}
+
+	/// Copy of the newest complete frame, null before the first one
+	#[napi]
+	pub fn read_frame(&self) -> Option<SharedFrame> {
+		let mut data = Vec::new();
+		let info = self.reader.read_frame(&mut data)?;
+		Some(SharedFrame {
+			width: info.width,
+			height: info.height,
+			format: info.format,
+			frame: info.frame as i64,
+			data: Uint8Array::new(data),
+		})
+	}
+}
+
 #[napi]
 pub fn native_version() -> String {
 	env!("CARGO_PKG_VERSION").to_string()
@@ -35,6 +84,18 @@ pub fn native_version() -> String {
 
 #[napi]
 pub fn create_shared_memory(size: u32) -> Result<SharedMemoryHandle> {
+	let (name, ptr) = allocate_shared_memory(size)?;
+	let name_for_finalize = name.clone();
+	let buffer = unsafe {
+		Uint8Array::with_external_data(ptr, size as usize, move |_data, _len| {
+			close_shared_memory_internal(&name_for_finalize);
+		})
+	};
+	Ok(SharedMemoryHandle { name, size, buffer })
+}
+
+// Creates and maps a new segment, tracked in SHM_MAP until it's closed
+fn allocate_shared_memory(size: u32) -> Result<(String, *mut u8)> {
 	if size == 0 {
 		return Err(Error::new(Status::InvalidArg, "size must be greater than 0".to_string()));
 	}
@@ -99,14 +160,7 @@ pub fn create_shared_memory(size: u32) -> Result<SharedMemoryHandle> {
 			map.insert(name.clone(), mapping);
 		}
 
-		let name_for_finalize = name.clone();
-		let buffer = unsafe {
-			Uint8Array::with_external_data(ptr, size_usize, move |_data, _len| {
-				close_shared_memory_internal(&name_for_finalize);
-			})
-		};
-
-		return Ok(SharedMemoryHandle { name, size, buffer });
+		return Ok((name, ptr));
 	}
 
 	Err(Error::new(
@@ -115,6 +169,143 @@ pub fn create_shared_memory(size: u32) -> Result<SharedMemoryHandle> {
 	))
 }
 
+/// Attaches to the frame segment `name` read-only.
+#[napi]
+pub fn open_shared_memory(name: String) -> Result<SharedFrameReader> {
+	let reader = FrameSegmentReader::open(&name).map_err(|err| {
+		Error::new(
+			Status::GenericFailure,
+			format!("unable to open frame segment {name}: {err}"),
+		)
+	})?;
+	Ok(SharedFrameReader { reader })
+}
+
+/// Unset values are left out of the sequence. Reusing `imageId` and
+/// `placementId` replaces the previous placement.
+#[napi(object)]
+pub struct KittyPlacementOptions {
+	pub image_id: Option<u32>,
+	pub placement_id: Option<u32>,
+	pub columns: Option<u32>,
+	pub rows: Option<u32>,
+	pub z_index: Option<i32>,
+}
+
+#[napi(object)]
+pub struct KittyImageOptions {
+	/// "s" shared memory, "f" file or "d" direct
+	pub transport: String,
+	pub width: u32,
+	pub height: u32,
+	/// 24 RGB or 32 RGBA, 24 by default
+	pub format: Option<u32>,
+	/// File the pixels are written to, required for "f"
+	pub path: Option<String>,
+	/// Sends the pixels zlib compressed (o=z)
+	pub compress: Option<bool>,
+	pub placement: Option<KittyPlacementOptions>,
+}
+
+#[napi(object)]
+pub struct KittyImage {
+	pub sequence: String,
+	/// Segment created for "s", closed with `closeSharedMemory()` once the terminal has read it
+	pub name: Option<String>,
+}
+
+/// Builds the escape sequence transmitting and displaying `frame`, writing
+/// the pixels to a new shared memory segment or to `path` first for the
+/// "s" and "f" transports.
+#[napi]
+pub fn encode_kitty_image(frame: Uint8Array, options: KittyImageOptions) -> Result<KittyImage> {
+	let format = options.format.unwrap_or(24);
+	if format != 24 && format != 32 {
+		return Err(Error::new(Status::InvalidArg, format!("unsupported pixel format {format}")));
+	}
+	let size = options.width as usize * options.height as usize * (format / 8) as usize;
+	if size == 0 || frame.len() < size {
+		return Err(Error::new(
+			Status::InvalidArg,
+			format!("frame must be {size} bytes, got {}", frame.len()),
+		));
+	}
+	let image = ImageFormat {
+		format,
+		width: options.width,
+		height: options.height,
+		compressed: options.compress.unwrap_or(false),
+	};
+	let compressed;
+	let data = match image.compressed {
+		true => {
+			compressed = kitty::compress(&frame[..size]);
+			&compressed[..]
+		}
+		false => &frame[..size],
+	};
+	let placement = to_placement(options.placement);
+	match options.transport.as_str() {
+		"d" => Ok(KittyImage {
+			sequence: kitty::encode_transmit(&Transport::Direct(data), &image, &placement),
+			name: None,
+		}),
+		"f" => {
+			let path = options
+				.path
+				.ok_or_else(|| Error::new(Status::InvalidArg, "the file transport needs a path".to_string()))?;
+			std::fs::write(&path, data)
+				.map_err(|err| Error::new(Status::GenericFailure, format!("unable to write {path}: {err}")))?;
+			let transport = Transport::File {
+				path: &path,
+				size: data.len(),
+			};
+			Ok(KittyImage {
+				sequence: kitty::encode_transmit(&transport, &image, &placement),
+				name: None,
+			})
+		}
+		"s" => {
+			let (name, ptr) = allocate_shared_memory(data.len() as u32)?;
+			unsafe {
+				std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+			}
+			let transport = Transport::SharedMemory {
+				name: &name,
+				size: data.len(),
+			};
+			Ok(KittyImage {
+				sequence: kitty::encode_transmit(&transport, &image, &placement),
+				name: Some(name),
+			})
+		}
+		transport => Err(Error::new(Status::InvalidArg, format!("unknown transport {transport}"))),
+	}
+}
+
+/// Shows an already transmitted image again (a=p) without resending it.
+#[napi]
+pub fn encode_kitty_placement(options: KittyPlacementOptions) -> String {
+	kitty::encode_placement(&to_placement(Some(options)))
+}
+
+/// Deletes the placements of `imageId`, only `placementId` when given, or
+/// every visible placement without an image. `free` also drops the image data.
+#[napi]
+pub fn encode_kitty_delete(image_id: Option<u32>, placement_id: Option<u32>, free: Option<bool>) -> String {
+	kitty::encode_delete(image_id, placement_id, free.unwrap_or(false))
+}
+
+fn to_placement(options: Option<KittyPlacementOptions>) -> Placement {
+	options.map_or_else(Placement::default, |options| Placement {
+		image_id: options.image_id,
+		placement_id: options.placement_id,
+		columns: options.columns,
+		rows: options.rows,
+		z_index: options.z_index,
+	})
+}
+
 #[napi]
 pub fn close_shared_memory(name: String) -> Result<bool> {
 	Ok(close_shared_memory_internal(&name))
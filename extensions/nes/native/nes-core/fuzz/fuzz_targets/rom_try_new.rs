@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nes_rust::rom::Rom;
+
+// Rom::try_new must reject malformed images instead of panicking, and any
+// image it accepts must survive the bus accesses Rom serves: CHR at
+// 0x0000 - 0x1FFF and PRG at 0x8000 - 0xFFFF, where stores hit the mapper.
+fuzz_target!(|data: &[u8]| {
+	if let Ok(mut rom) = Rom::try_new(data.to_vec()) {
+		for address in (0..0x2000).chain(0x8000..0x10000).step_by(0x3f) {
+			rom.load(address);
+			rom.store(address | 0x8000, address as u8);
+			rom.load_chr(address & 0x1fff);
+			rom.store_chr(address & 0x1fff, 0);
+		}
+	}
+});
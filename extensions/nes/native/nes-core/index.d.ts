@@ -7,6 +7,7 @@ export interface CpuDebugState {
   p: number;
   lastPc: number;
   lastOpcode: number;
+  jammed: boolean;
 }
 
 export interface MapperDebugState {
@@ -20,6 +21,145 @@ export interface MapperDebugState {
   outerPrg: number;
 }
 
+/** Kinds: 0 execute, 1 CPU data read (not instruction fetches), 2 CPU write, 3 VRAM write through $2007. */
+export interface BreakpointOptions {
+  kind: number;
+  address: number;
+  /** Inclusive, defaults to `address` */
+  endAddress?: number;
+  /** eg. "A == $3F && [$0075] > 2" */
+  condition?: string;
+  /** Breaks from this hit on */
+  minHits?: number;
+}
+
+export interface BreakpointInfo {
+  id: number;
+  kind: number;
+  address: number;
+  endAddress: number;
+  condition?: string;
+  minHits: number;
+  hits: number;
+}
+
+export interface BreakpointHit {
+  id: number;
+  kind: number;
+  address: number;
+  /** Accessed byte, 0 for execute breakpoints */
+  value: number;
+  cpu: CpuDebugState;
+}
+
+export interface DisassembledInstruction {
+  address: number;
+  bytes: Array<number>;
+  mnemonic: string;
+  operand: string;
+  effectiveAddress?: number;
+  length: number;
+  unofficial: boolean;
+}
+
+export interface CheatInfo {
+  id: number;
+  code: string;
+  /** "game-genie", "pro-action-replay" or "raw" */
+  format: string;
+  address: number;
+  value: number;
+  compare?: number;
+  enabled: boolean;
+}
+
+export interface OamSpriteInfo {
+  index: number;
+  x: number;
+  /** Top scanline minus one, as stored in OAM */
+  y: number;
+  tile: number;
+  attributes: number;
+  palette: number;
+  behindBackground: boolean;
+  flipHorizontal: boolean;
+  flipVertical: boolean;
+  /** 8 or 16 */
+  height: number;
+}
+
+export interface RamSearchResult {
+  address: number;
+  value: number;
+  previous: number;
+}
+
+/** Unset values come from the preset: 0 composite direct, 1 FCEUX, 2 Nestopia, 3 Smooth */
+export interface PaletteOptions {
+  preset?: number;
+  /** Degrees */
+  hue?: number;
+  saturation?: number;
+  contrast?: number;
+  /** -1.0 - 1.0 */
+  brightness?: number;
+  /** Display gamma, 2.2 leaves the levels alone */
+  gamma?: number;
+}
+
+/**
+ * Unset values come from the preset: 0 composite, 1 S-Video, 2 RGB.
+ * Effects range from -1.0 (off) through 0.0 (composite TV) to 1.0.
+ */
+export interface NtscFilterOptions {
+  preset?: number;
+  /** Output pixels a scanline, 256-2048, defaults to 602 */
+  width?: number;
+  artifacts?: number;
+  fringing?: number;
+  bleed?: number;
+  sharpness?: number;
+  /** Averages the two fields so artifacts don't crawl */
+  mergeFields?: boolean;
+  /** Degrees */
+  hue?: number;
+  saturation?: number;
+  contrast?: number;
+  brightness?: number;
+  gamma?: number;
+}
+
+/** Frames are `[startFrame, endFrame)`, PCs are `[startPc, endPc]`. */
+export interface TraceOptions {
+  startFrame?: number;
+  endFrame?: number;
+  startPc?: number;
+  endPc?: number;
+}
+
+export interface MovieStatus {
+  /** "idle", "recording", "playing" or "finished" */
+  mode: string;
+  frame: number;
+  length: number;
+  desyncFrame?: number;
+}
+
+/** Pixels cropped a side, 0-64 and 0 when unset. */
+export interface OverscanOptions {
+  top?: number;
+  bottom?: number;
+  left?: number;
+  right?: number;
+  /** Stretches to the 8:7 pixel aspect ratio, 256 pixels wide becomes 292 */
+  aspectCorrection?: boolean;
+}
+
+export interface FramebufferSize {
+  width: number;
+  height: number;
+}
+
 export interface NesDebugState {
   cpu: CpuDebugState;
   mapper: MapperDebugState;
@@ -28,10 +168,59 @@ export interface NesDebugState {
 export class NativeNes {
   constructor();
   setRom(data: Uint8Array): void;
+  /**
+   * Powers the machine on. While recording a movie this is a power cycle
+   * which reloads the rom, like the movie's power command.
+   */
   bootup(): void;
+  /** Presses the console's reset button. */
+  reset(): void;
   stepFrame(): void;
   refreshFramebuffer(): void;
+  /**
+   * Upscales the framebuffer after the video filter: 0 off, 1 nearest neighbour, 2 Scale2x, 3 Scale3x,
+   * 4 hq2x, 5 hq3x, 6 xBRZ. Nearest neighbour and xBRZ take a 2-6 `factor`, 2 by default.
+   * `getFramebuffer()` has to be called again for the new size.
+   */
+  setScaler(mode: number, factor?: number | undefined | null): void;
+  /**
+   * Crops the overscan before upscaling, or shows the whole picture with no options.
+   * The sides are in NES pixels, scaled to the NTSC filter's width while it's on.
+   * `getFramebuffer()` has to be called again for the new size.
+   */
+  setOverscan(options?: OverscanOptions | undefined | null): void;
+  /**
+   * Publishes every frame of `getFramebuffer()` to the named POSIX shared memory segment `name`, or removes the
+   * segment with no name. Other processes read it with the kitty-shm addon's `openSharedMemory(name)`.
+   */
+  setSharedFramebuffer(name?: string | undefined | null): void;
+  /** Size of the frames `getFramebuffer()` returns, after the NTSC filter, cropping and upscaling */
+  getFramebufferSize(): FramebufferSize;
+  setRunAhead(frames: number, secondInstance?: boolean | undefined | null): void;
   setVideoFilter(mode: number): void;
+  /**
+   * Signal level NTSC filter in place of the framebuffer and video filter, `width` x 240 before cropping and
+   * upscaling. Disabled without options. `getFramebuffer()` has to be called again for the new size.
+   */
+  setNtscFilter(options?: NtscFilterOptions | undefined | null): void;
+  /** Keeps the palette indices of each frame for `getPaletteIndices()` */
+  setPaletteIndexOutput(enabled: boolean): void;
+  /**
+   * 256x240 view of the last frame before palette lookup: the 6-bit color in bits 0-5 and the PPUMASK
+   * emphasis bits (red, green, blue) in bits 6-8. Only updated while enabled or the NTSC filter is on.
+   */
+  getPaletteIndices(): Uint16Array;
+  /** Disabling the 8 sprites per scanline limit draws every sprite, without changing overflow or sprite 0 hit */
+  setSpriteLimit(enabled: boolean): void;
+  /** .pal file contents, 192 bytes or 1536 bytes with the emphasis colors */
+  setPalette(data: Uint8Array): void;
+  generatePalette(options?: PaletteOptions | undefined | null): void;
+  resetPalette(): void;
+  /** Bits as in PPUMASK, set to show: 0x08 background, 0x10 sprites. Hidden sprites are OAM indices 0-63 */
+  setLayerMask(bits: number, hiddenSprites?: Array<number> | undefined | null): void;
+  setRegion(mode: number): void;
+  getRegion(): number;
+  getFrameRate(): number;
   setAudioEnabled(enabled: boolean): boolean;
   pressButton(button: number): void;
   releaseButton(button: number): void;
@@ -40,6 +229,50 @@ export class NativeNes {
   setSram(data: Uint8Array): void;
   isSramDirty(): boolean;
   markSramSaved(): void;
+  saveState(): Uint8Array;
+  loadState(data: Uint8Array): void;
+  setRewind(budgetBytes?: number | undefined | null, frameInterval?: number | undefined | null): void;
+  getRewindFrames(): number;
+  rewind(frames: number): number;
+  startMovieRecording(fromPowerOn: boolean, checkpointInterval?: number | undefined | null): void;
+  startMoviePlayback(fm2: string): void;
+  stopMovie(): void;
+  exportMovie(romFilename?: string | undefined | null): string;
+  getMovieStatus(): MovieStatus;
+  startTrace(options?: TraceOptions | undefined | null): void;
+  stopTrace(): void;
+  isTracing(): boolean;
+  takeTrace(): string;
+  addBreakpoint(options: BreakpointOptions): number;
+  removeBreakpoint(id: number): boolean;
+  clearBreakpoints(): void;
+  getBreakpoints(): Array<BreakpointInfo>;
+  getBreakHit(): BreakpointHit | null;
+  /** Game Genie (SXIOPO), Pro Action Replay (00075A09) or raw (075A:09, C123?20:EA) codes */
+  addCheat(code: string): number;
+  removeCheat(id: number): boolean;
+  setCheatEnabled(id: number, enabled: boolean): boolean;
+  listCheats(): Array<CheatInfo>;
+  /** Debug viewers render RGB into caller buffers: pattern tables 128x128, nametables 512x480, palettes 128x16, sprites 64x128 */
+  renderPatternTable(table: number, palette: number, buffer: Uint8Array): void;
+  renderNameTables(buffer: Uint8Array, scrollOverlay?: boolean | undefined | null): void;
+  renderPalettes(buffer: Uint8Array): void;
+  getOamSprites(): Array<OamSpriteInfo>;
+  renderSprites(buffer: Uint8Array): void;
+  /** Widths: 1 or 2 bytes. Encodings: 0 unsigned, 1 signed, 2 BCD */
+  startRamSearch(width: number, encoding: number): number;
+  /** Comparisons: 0 equal, 1 not equal, 2 greater, 3 less, 4 changed by operand, 5 equal to operand */
+  filterRamSearch(comparison: number, operand?: number | undefined | null): number;
+  getRamSearchResults(limit?: number | undefined | null): Array<RamSearchResult>;
+  getRamSearchCount(): number;
+  stopRamSearch(): void;
+  disassemble(address: number, count: number, prgOffset?: number | undefined | null): Array<DisassembledInstruction>;
+  getFrame(): number;
+  /** Domains: 0 CPU bus, 1 internal RAM, 2 PRG-ROM, 3 PRG-RAM, 4 CHR, 5 nametables, 6 palette, 7 OAM */
+  peek(domain: number, address: number): number | null;
+  poke(domain: number, address: number, value: number): boolean;
+  readRange(domain: number, address: number, length: number): Uint8Array;
+  getMemoryDomainSize(domain: number): number;
   getDebugState(): NesDebugState;
   getFramebuffer(): Uint8Array;
 }
@@ -1,13 +1,41 @@
-use napi::bindgen_prelude::Uint8Array;
+use napi::bindgen_prelude::{Uint16Array, Uint8Array};
+use napi::{Error, Result, Status};
 use napi_derive::napi;
 use nes_rust::audio::Audio;
+use nes_rust::breakpoint::{self, Breakpoint, BreakpointKind};
 use nes_rust::button::Button;
-#[cfg(not(feature = "audio-cpal"))]
+use nes_rust::cheat::CheatFormat;
 use nes_rust::default_audio::DefaultAudio;
 use nes_rust::default_input::DefaultInput;
+use nes_rust::expression::Expression;
 use nes_rust::display::{Display, SCREEN_HEIGHT, SCREEN_WIDTH};
+use nes_rust::memory::MemoryDomain;
+use nes_rust::palette::{self, NtscPaletteParams, PaletteError, PalettePreset};
+use nes_rust::ppu::{
+	LayerMask, NAME_TABLES_HEIGHT, NAME_TABLES_WIDTH, PALETTE_VIEW_HEIGHT, PALETTE_VIEW_WIDTH, PATTERN_TABLE_HEIGHT,
+	PATTERN_TABLE_WIDTH, SPRITE_THUMBNAIL_HEIGHT, SPRITE_THUMBNAIL_WIDTH,
+};
+use nes_rust::ram_search::{RamSearch, SearchComparison, SearchEncoding, SearchWidth};
+use nes_rust::region::Region;
 use nes_rust::rom::Rom;
+use nes_rust::state::StateError;
+use nes_rust::trace::{TraceFilter, Tracer};
 use nes_rust::Nes;
+use pi_nes_frame_segment::{FrameSegmentWriter, FORMAT_RGB};
+
+mod movie;
+mod ntsc;
+mod overscan;
+mod rewind;
+mod scale;
+mod trace;
+
+use movie::{Movie, MovieAnchor, MovieSession, COMMAND_POWER, COMMAND_SOFT_RESET, DEFAULT_CHECKPOINT_INTERVAL};
+use ntsc::{NtscFilter, NtscFilterParams, DEFAULT_NTSC_WIDTH, MAX_NTSC_WIDTH, MIN_NTSC_WIDTH};
+use overscan::{FrameCrop, Overscan, ASPECT_WIDTH, MAX_FRAME_BYTE_LEN, MAX_OVERSCAN};
+use rewind::{RewindBuffer, DEFAULT_REWIND_BUDGET_BYTES, DEFAULT_REWIND_INTERVAL};
+use scale::{Scaler, Upscaler, MAX_SCALE_FACTOR};
+use trace::{TraceBuffer, DEFAULT_TRACE_BUFFER_BYTES};
 
 #[cfg(feature = "audio-cpal")]
 mod audio_cpal;
@@ -15,6 +43,16 @@ mod audio_cpal;
 use audio_cpal::CpalAudio;
 
 const FRAME_BYTE_LEN: usize = (SCREEN_WIDTH * SCREEN_HEIGHT * 3) as usize;
+// The 8:7 picture at the largest scale factor. Settings making larger frames
+// are refused, the scaled framebuffer and shared segment slots are this size.
+const MAX_SCALED_FRAME_BYTE_LEN: usize =
+	(ASPECT_WIDTH * SCREEN_HEIGHT * 3) as usize * (MAX_SCALE_FACTOR * MAX_SCALE_FACTOR) as usize;
+const MAX_RUN_AHEAD_FRAMES: u32 = 8;
+const MAX_DISASSEMBLY_COUNT: u32 = 4096;
+const MAX_RAM_SEARCH_RESULTS: u32 = 4096;
+// The sprite viewer lays the 64 thumbnails out 8 a row, 64x128 pixels
+const SPRITE_ATLAS_COLUMNS: u32 = 8;
+const SPRITE_THUMBNAIL_BYTE_LEN: usize = (SPRITE_THUMBNAIL_WIDTH * SPRITE_THUMBNAIL_HEIGHT * 3) as usize;
 
 enum AudioBackend {
 	#[cfg(feature = "audio-cpal")]
@@ -24,6 +62,7 @@ enum AudioBackend {
 }
 
 impl AudioBackend {
+	#[cfg_attr(not(feature = "audio-cpal"), allow(unused_variables))]
 	fn set_enabled(&self, enabled: bool) -> bool {
 		match self {
 			#[cfg(feature = "audio-cpal")]
@@ -38,13 +77,13 @@ fn create_audio_backend() -> (Box<dyn Audio>, AudioBackend) {
 	#[cfg(feature = "audio-cpal")]
 	{
 		let audio = CpalAudio::new();
-		return (Box::new(audio.clone()), AudioBackend::Cpal(audio));
+		(Box::new(audio.clone()), AudioBackend::Cpal(audio))
 	}
 
 	#[cfg(not(feature = "audio-cpal"))]
 	{
 		let audio = DefaultAudio::new();
-		return (Box::new(audio), AudioBackend::Default);
+		(Box::new(audio), AudioBackend::Default)
 	}
 }
 
@@ -65,12 +104,14 @@ struct VideoFilterConfig {
 
 struct NativeDisplay {
 	pixels: Vec<u8>,
+	palette_indices: Vec<u16>,
 }
 
 impl NativeDisplay {
 	fn new() -> Self {
 		Self {
 			pixels: vec![0; FRAME_BYTE_LEN],
+			palette_indices: vec![0; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize],
 		}
 	}
 }
@@ -91,6 +132,18 @@ impl Display for NativeDisplay {
 		self.pixels[base_index + 2] = b;
 	}
 
+	fn render_palette_index(&mut self, x: u16, y: u16, index: u16) {
+		if x >= SCREEN_WIDTH as u16 || y >= SCREEN_HEIGHT as u16 {
+			return;
+		}
+		self.palette_indices[y as usize * SCREEN_WIDTH as usize + x as usize] = index;
+	}
+
+	fn copy_palette_indices(&self, indices: &mut [u16]) {
+		let len = indices.len().min(self.palette_indices.len());
+		indices[..len].copy_from_slice(&self.palette_indices[..len]);
+	}
+
 	fn vblank(&mut self) {}
 
 	fn copy_to_rgba_pixels(&self, pixels: &mut [u8]) {
@@ -109,6 +162,132 @@ pub struct CpuDebugState {
 	pub p: u8,
 	pub last_pc: u16,
 	pub last_opcode: u8,
+	pub jammed: bool,
+}
+
+/// Kinds: 0 execute, 1 CPU data read (not instruction fetches), 2 CPU write, 3 VRAM write through $2007.
+#[napi(object)]
+pub struct BreakpointOptions {
+	pub kind: u8,
+	pub address: u16,
+	/// Inclusive, defaults to `address`
+	pub end_address: Option<u16>,
+	/// eg. "A == $3F && [$0075] > 2"
+	pub condition: Option<String>,
+	/// Breaks from this hit on
+	pub min_hits: Option<u32>,
+}
+
+#[napi(object)]
+pub struct BreakpointInfo {
+	pub id: u32,
+	pub kind: u8,
+	pub address: u16,
+	pub end_address: u16,
+	pub condition: Option<String>,
+	pub min_hits: u32,
+	pub hits: u32,
+}
+
+#[napi(object)]
+pub struct BreakpointHit {
+	pub id: u32,
+	pub kind: u8,
+	pub address: u16,
+	/// Accessed byte, 0 for execute breakpoints
+	pub value: u8,
+	pub cpu: CpuDebugState,
+}
+
+#[napi(object)]
+pub struct DisassembledInstruction {
+	pub address: u16,
+	pub bytes: Vec<u8>,
+	pub mnemonic: String,
+	pub operand: String,
+	pub effective_address: Option<u16>,
+	pub length: u8,
+	pub unofficial: bool,
+}
+
+#[napi(object)]
+pub struct CheatInfo {
+	pub id: u32,
+	pub code: String,
+	/// "game-genie", "pro-action-replay" or "raw"
+	pub format: String,
+	pub address: u16,
+	pub value: u8,
+	pub compare: Option<u8>,
+	pub enabled: bool,
+}
+
+#[napi(object)]
+pub struct OamSpriteInfo {
+	pub index: u8,
+	pub x: u8,
+	/// Top scanline minus one, as stored in OAM
+	pub y: u8,
+	pub tile: u8,
+	pub attributes: u8,
+	pub palette: u8,
+	pub behind_background: bool,
+	pub flip_horizontal: bool,
+	pub flip_vertical: bool,
+	/// 8 or 16
+	pub height: u8,
+}
+
+#[napi(object)]
+pub struct RamSearchResult {
+	pub address: u16,
+	pub value: i64,
+	pub previous: i64,
+}
+
+/// Unset values come from the preset: 0 composite direct, 1 FCEUX,
+/// 2 Nestopia, 3 Smooth.
+#[napi(object)]
+pub struct PaletteOptions {
+	pub preset: Option<u8>,
+	/// Degrees
+	pub hue: Option<f64>,
+	pub saturation: Option<f64>,
+	pub contrast: Option<f64>,
+	/// -1.0 - 1.0
+	pub brightness: Option<f64>,
+	/// Display gamma, 2.2 leaves the levels alone
+	pub gamma: Option<f64>,
+}
+
+/// Unset values come from the preset: 0 composite, 1 S-Video, 2 RGB.
+/// Effects range from -1.0 (off) through 0.0 (composite TV) to 1.0.
+#[napi(object)]
+pub struct NtscFilterOptions {
+	pub preset: Option<u8>,
+	/// Output pixels a scanline, 256-2048, defaults to 602
+	pub width: Option<u32>,
+	pub artifacts: Option<f64>,
+	pub fringing: Option<f64>,
+	pub bleed: Option<f64>,
+	pub sharpness: Option<f64>,
+	/// Averages the two fields so artifacts don't crawl
+	pub merge_fields: Option<bool>,
+	/// Degrees
+	pub hue: Option<f64>,
+	pub saturation: Option<f64>,
+	pub contrast: Option<f64>,
+	pub brightness: Option<f64>,
+	pub gamma: Option<f64>,
+}
+
+/// Frames are `[startFrame, endFrame)`, PCs are `[startPc, endPc]`.
+#[napi(object)]
+pub struct TraceOptions {
+	pub start_frame: Option<u32>,
+	pub end_frame: Option<u32>,
+	pub start_pc: Option<u16>,
+	pub end_pc: Option<u16>,
 }
 
 #[napi(object)]
@@ -123,6 +302,32 @@ pub struct MapperDebugState {
 	pub outer_prg: u8,
 }
 
+#[napi(object)]
+pub struct MovieStatus {
+	/// "idle", "recording", "playing" or "finished"
+	pub mode: String,
+	pub frame: u32,
+	pub length: u32,
+	pub desync_frame: Option<u32>,
+}
+
+/// Pixels cropped a side, 0-64 and 0 when unset.
+#[napi(object)]
+pub struct OverscanOptions {
+	pub top: Option<u32>,
+	pub bottom: Option<u32>,
+	pub left: Option<u32>,
+	pub right: Option<u32>,
+	/// Stretches to the 8:7 pixel aspect ratio, 256 pixels wide becomes 292
+	pub aspect_correction: Option<bool>,
+}
+
+#[napi(object)]
+pub struct FramebufferSize {
+	pub width: u32,
+	pub height: u32,
+}
+
 #[napi(object)]
 pub struct NesDebugState {
 	pub cpu: CpuDebugState,
@@ -135,7 +340,41 @@ pub struct NativeNes {
 	framebuffer: Vec<u8>,
 	filter_buffer: Vec<u8>,
 	video_filter: VideoFilterMode,
+	// Replaces the framebuffer as the first stage of the pipeline when set
+	ntsc_filter: Option<NtscFilter>,
+	// Sized for the widest filter on first use so views of it stay valid
+	ntsc_framebuffer: Vec<u8>,
+	// Palette index and emphasis bits per pixel, feeds the NTSC filter
+	palette_indices: Vec<u16>,
+	palette_index_output: bool,
+	crop: Option<FrameCrop>,
+	// Sized for the widest crop on first use so views of it stay valid
+	cropped_framebuffer: Vec<u8>,
+	upscaler: Option<Upscaler>,
+	// Sized for the largest factor on first use so views of it stay valid
+	scaled_framebuffer: Vec<u8>,
+	shared_framebuffer: Option<FrameSegmentWriter>,
 	audio_backend: AudioBackend,
+	rewind: RewindBuffer,
+	rom_data: Vec<u8>,
+	run_ahead_frames: u32,
+	// Runs the speculative frames in second-instance mode so the main
+	// machine never rolls back and its audio stream stays continuous.
+	run_ahead_instance: Option<Nes>,
+	movie: MovieSession,
+	// None follows the region in the rom header
+	region_override: Option<Region>,
+	header_region: Region,
+	trace_buffer: Option<TraceBuffer>,
+	// Set while a breakpoint has stopped the machine mid-frame
+	break_hit: Option<breakpoint::BreakpointHit>,
+	ram_search: Option<RamSearch>,
+}
+
+impl Default for NativeNes {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 #[napi]
@@ -151,33 +390,364 @@ impl NativeNes {
 			framebuffer: vec![0; FRAME_BYTE_LEN],
 			filter_buffer: vec![0; FRAME_BYTE_LEN],
 			video_filter: VideoFilterMode::Off,
+			ntsc_filter: None,
+			ntsc_framebuffer: Vec::new(),
+			palette_indices: vec![0; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize],
+			palette_index_output: false,
+			crop: None,
+			cropped_framebuffer: Vec::new(),
+			upscaler: None,
+			scaled_framebuffer: Vec::new(),
+			shared_framebuffer: None,
 			audio_backend,
+			rewind: RewindBuffer::new(0, DEFAULT_REWIND_INTERVAL),
+			rom_data: Vec::new(),
+			run_ahead_frames: 0,
+			run_ahead_instance: None,
+			movie: MovieSession::new(),
+			region_override: None,
+			header_region: Region::Ntsc,
+			trace_buffer: None,
+			break_hit: None,
+			ram_search: None,
 		}
 	}
 
 	#[napi]
-	pub fn set_rom(&mut self, data: napi::bindgen_prelude::Uint8Array) {
-		let rom = Rom::new(data.to_vec());
+	pub fn set_rom(&mut self, data: napi::bindgen_prelude::Uint8Array) -> Result<()> {
+		let rom = Rom::try_new(data.to_vec())
+			.map_err(|err| Error::new(Status::InvalidArg, format!("Invalid ROM: {}", err)))?;
+		self.rom_data = data.to_vec();
+		self.header_region = Region::from_timing_mode(rom.header().timing_mode());
 		self.nes.set_rom(rom);
+		// Codes are specific to a game
+		self.nes.clear_cheats();
+		self.ram_search = None;
+		self.rewind.clear();
+		self.break_hit = None;
+		if self.run_ahead_instance.is_some() {
+			self.run_ahead_instance = Some(create_run_ahead_instance(&self.rom_data, &self.nes));
+		}
+		self.apply_region(self.region());
+		Ok(())
 	}
 
+	/// Powers the machine on. While recording a movie this is a power cycle
+	/// which reloads the rom, like the movie's power command.
 	#[napi]
 	pub fn bootup(&mut self) {
-		self.nes.bootup();
+		match self.movie.is_recording() {
+			true => {
+				self.power_on();
+				self.movie.record_command(COMMAND_POWER);
+			}
+			false => self.nes.bootup(),
+		}
+		self.break_hit = None;
 	}
 
+	/// Presses the console's reset button.
 	#[napi]
-	pub fn step_frame(&mut self) {
-		self.nes.step_frame();
+	pub fn reset(&mut self) {
+		self.nes.reset();
+		self.movie.record_command(COMMAND_SOFT_RESET);
+		self.break_hit = None;
+	}
+
+	/// Runs a frame, or the rest of it after a breakpoint stopped the last
+	/// call. Check `get_break_hit()` afterwards to see if one stopped it again.
+	#[napi]
+	pub fn step_frame(&mut self) -> Result<()> {
+		let resuming = self.break_hit.take().is_some();
+		let input = match resuming {
+			true => None,
+			false => self.movie.next_frame(),
+		};
+		if let Some(input) = input {
+			if input.commands & COMMAND_POWER != 0 {
+				self.power_on();
+			} else if input.commands & COMMAND_SOFT_RESET != 0 {
+				self.nes.reset();
+			}
+			self.nes.set_joypad_state(0, input.ports[0]);
+			self.nes.set_joypad_state(1, input.ports[1]);
+		}
+		let mut snapshot = None;
+		if self.run_ahead_frames == 0 {
+			self.break_hit = self.nes.step_frame();
+		} else if let Some(instance) = self.run_ahead_instance.as_mut() {
+			self.nes.set_video_output_enabled(false);
+			self.break_hit = self.nes.step_frame();
+			self.nes.set_video_output_enabled(true);
+			if self.break_hit.is_none() {
+				let state = self.nes.save_state();
+				instance.load_state(&state).map_err(state_error)?;
+				for _ in 0..self.run_ahead_frames {
+					instance.step_frame();
+				}
+				snapshot = Some(state);
+			}
+		} else {
+			// The real frame keeps audio, the speculative ones keep
+			// only the last frame's picture.
+			self.nes.set_video_output_enabled(false);
+			self.break_hit = self.nes.step_frame();
+			if self.break_hit.is_none() {
+				let state = self.nes.save_state();
+				self.nes.set_audio_output_enabled(false);
+				// Speculative frames are replayed for real later, keep them
+				// out of the trace and the breakpoints
+				let tracer = self.nes.stop_trace();
+				self.nes.set_breakpoints_enabled(false);
+				for frame in 0..self.run_ahead_frames {
+					self.nes.set_video_output_enabled(frame + 1 == self.run_ahead_frames);
+					self.nes.step_frame();
+				}
+				self.nes.set_breakpoints_enabled(true);
+				if let Some(tracer) = tracer {
+					self.nes.start_trace(tracer);
+				}
+				self.nes.set_audio_output_enabled(true);
+				self.nes.load_state(&state).map_err(state_error)?;
+				snapshot = Some(state);
+			}
+			self.nes.set_video_output_enabled(true);
+		}
+		if self.break_hit.is_some() {
+			return Ok(());
+		}
+		self.movie.end_frame(&self.nes);
+		let nes = &self.nes;
+		self.rewind.record_frame(|| snapshot.unwrap_or_else(|| nes.save_state()));
+		Ok(())
 	}
 
 	#[napi]
 	pub fn refresh_framebuffer(&mut self) {
-		self.nes.copy_pixels(&mut self.framebuffer);
-		if let Some(config) = video_filter_config(self.video_filter) {
-			self.filter_buffer.copy_from_slice(&self.framebuffer);
-			apply_video_filter(&self.filter_buffer, &mut self.framebuffer, &config);
+		let presenting = match (self.run_ahead_frames, self.run_ahead_instance.as_ref()) {
+			(0, _) | (_, None) => &self.nes,
+			(_, Some(instance)) => instance,
+		};
+		if self.palette_index_output || self.ntsc_filter.is_some() {
+			presenting.copy_palette_indices(&mut self.palette_indices);
+		}
+		let (width, height) = self.cropped_size();
+		let picture = match self.ntsc_filter.as_mut() {
+			Some(filter) => {
+				let picture = &mut self.ntsc_framebuffer[..filter.frame_len()];
+				filter.render(&self.palette_indices, picture);
+				picture
+			}
+			None => {
+				presenting.copy_pixels(&mut self.framebuffer);
+				if let Some(config) = video_filter_config(self.video_filter) {
+					self.filter_buffer.copy_from_slice(&self.framebuffer);
+					apply_video_filter(&self.filter_buffer, &mut self.framebuffer, &config);
+				}
+				&mut self.framebuffer[..]
+			}
+		};
+		let frame = match self.crop.as_ref() {
+			Some(crop) => {
+				let cropped = &mut self.cropped_framebuffer[..crop.frame_len()];
+				crop.apply(picture, cropped);
+				cropped
+			}
+			None => picture,
+		};
+		if let Some(upscaler) = self.upscaler.as_mut() {
+			let factor = upscaler.factor();
+			let len = (width * height * 3 * factor * factor) as usize;
+			upscaler.scale(
+				frame,
+				width as usize,
+				height as usize,
+				&mut self.scaled_framebuffer[..len],
+			);
+		}
+		if let Some(mut segment) = self.shared_framebuffer.take() {
+			let size = self.get_framebuffer_size();
+			// Slots fit the largest frame, so this can't fail
+			let _ = segment.write_frame(size.width, size.height, self.output_frame());
+			self.shared_framebuffer = Some(segment);
+		}
+	}
+
+	/// Publishes every frame of `getFramebuffer()` to the named POSIX shared
+	/// memory segment `name`, or removes the segment with no name. Other
+	/// processes read it with the kitty-shm addon's `openSharedMemory(name)`.
+	#[napi]
+	pub fn set_shared_framebuffer(&mut self, name: Option<String>) -> Result<()> {
+		self.shared_framebuffer = None;
+		if let Some(name) = name {
+			let segment = FrameSegmentWriter::create(&name, FORMAT_RGB, MAX_SCALED_FRAME_BYTE_LEN).map_err(|err| {
+				Error::new(
+					Status::GenericFailure,
+					format!("Unable to create frame segment {}: {}", name, err),
+				)
+			})?;
+			self.shared_framebuffer = Some(segment);
+			self.refresh_framebuffer();
+		}
+		Ok(())
+	}
+
+	/// Upscales the framebuffer after the video filter: 0 off, 1 nearest
+	/// neighbour, 2 Scale2x, 3 Scale3x, 4 hq2x, 5 hq3x, 6 xBRZ. Nearest
+	/// neighbour and xBRZ take a 2-6 `factor`, 2 by default. Fails if the
+	/// frames would be larger than the 8:7 picture upscaled 6 times.
+	/// `getFramebuffer()` has to be called again for the new size.
+	#[napi]
+	pub fn set_scaler(&mut self, mode: u8, factor: Option<u32>) -> Result<()> {
+		let factor = factor.unwrap_or(2);
+		if !(2..=MAX_SCALE_FACTOR).contains(&factor) {
+			return Err(Error::new(
+				Status::InvalidArg,
+				format!("Scale factor must be 2-{}, got {}", MAX_SCALE_FACTOR, factor),
+			));
+		}
+		let scaler = match mode {
+			0 => None,
+			1 => Some(Scaler::Nearest(factor)),
+			2 => Some(Scaler::Scale2x),
+			3 => Some(Scaler::Scale3x),
+			4 => Some(Scaler::Hq2x),
+			5 => Some(Scaler::Hq3x),
+			6 => Some(Scaler::Xbrz(factor)),
+			mode => {
+				return Err(Error::new(Status::InvalidArg, format!("Unknown scaler {}", mode)));
+			}
+		};
+		let (width, height) = self.cropped_size();
+		check_scaled_size(width, height, scaler.map_or(1, |scaler| scaler.factor()))?;
+		if scaler.is_some() && self.scaled_framebuffer.is_empty() {
+			self.scaled_framebuffer = vec![0; MAX_SCALED_FRAME_BYTE_LEN];
+		}
+		self.upscaler = scaler.map(Upscaler::new);
+		self.refresh_framebuffer();
+		Ok(())
+	}
+
+	/// Crops the overscan before upscaling, or shows the whole picture with no options.
+	/// The sides are in NES pixels, scaled to the NTSC filter's width while it's on.
+	/// `getFramebuffer()` has to be called again for the new size.
+	#[napi]
+	pub fn set_overscan(&mut self, options: Option<OverscanOptions>) -> Result<()> {
+		let crop = match options {
+			Some(options) => {
+				let overscan = Overscan {
+					top: options.top.unwrap_or(0),
+					bottom: options.bottom.unwrap_or(0),
+					left: options.left.unwrap_or(0),
+					right: options.right.unwrap_or(0),
+				};
+				let sides = [overscan.top, overscan.bottom, overscan.left, overscan.right];
+				if let Some(side) = sides.iter().find(|&&side| side > MAX_OVERSCAN) {
+					return Err(Error::new(
+						Status::InvalidArg,
+						format!("Overscan must be 0-{} pixels a side, got {}", MAX_OVERSCAN, side),
+					));
+				}
+				Some(FrameCrop::new(overscan, options.aspect_correction.unwrap_or(false), self.picture_width()))
+			}
+			None => None,
+		};
+		let (width, height) = crop.as_ref().map_or((self.picture_width(), SCREEN_HEIGHT), |crop| (crop.width(), crop.height()));
+		check_scaled_size(width, height, self.scale_factor())?;
+		self.crop = crop;
+		if self.crop.is_some() && self.cropped_framebuffer.is_empty() {
+			self.cropped_framebuffer = vec![0; MAX_FRAME_BYTE_LEN];
+		}
+		self.refresh_framebuffer();
+		Ok(())
+	}
+
+	/// Size of the frames `getFramebuffer()` returns, after the NTSC filter,
+	/// cropping and upscaling
+	#[napi]
+	pub fn get_framebuffer_size(&self) -> FramebufferSize {
+		let (width, height) = self.cropped_size();
+		let factor = self.scale_factor();
+		FramebufferSize {
+			width: width * factor,
+			height: height * factor,
+		}
+	}
+
+	/// Enables the signal level NTSC filter, or disables it with no options.
+	/// It replaces the framebuffer and the video filter as the picture going
+	/// into cropping and upscaling, `width` x 240. Fails if the upscaled frames
+	/// would be too large, like `setScaler()`.
+	/// `getFramebuffer()` has to be called again for the new size.
+	#[napi]
+	pub fn set_ntsc_filter(&mut self, options: Option<NtscFilterOptions>) -> Result<()> {
+		let options = match options {
+			Some(options) => options,
+			None => {
+				self.ntsc_filter = None;
+				self.crop = self.crop.take().map(|crop| crop.with_source_width(SCREEN_WIDTH));
+				self.refresh_framebuffer();
+				return Ok(());
+			}
+		};
+		let base = match options.preset.unwrap_or(0) {
+			0 => NtscFilterParams::composite(),
+			1 => NtscFilterParams::svideo(),
+			2 => NtscFilterParams::rgb(),
+			preset => {
+				return Err(Error::new(Status::InvalidArg, format!("Unknown NTSC filter preset {}", preset)));
+			}
+		};
+		let width = options.width.unwrap_or(DEFAULT_NTSC_WIDTH);
+		if !(MIN_NTSC_WIDTH..=MAX_NTSC_WIDTH).contains(&width) {
+			return Err(Error::new(
+				Status::InvalidArg,
+				format!("NTSC width must be {}-{}, got {}", MIN_NTSC_WIDTH, MAX_NTSC_WIDTH, width),
+			));
+		}
+		let option = |value: Option<f64>, default: f32| value.map_or(default, |value| value as f32);
+		let params = NtscFilterParams {
+			picture: NtscPaletteParams {
+				hue: option(options.hue, base.picture.hue),
+				saturation: option(options.saturation, base.picture.saturation),
+				contrast: option(options.contrast, base.picture.contrast),
+				brightness: option(options.brightness, base.picture.brightness),
+				gamma: option(options.gamma, base.picture.gamma),
+			},
+			artifacts: option(options.artifacts, base.artifacts),
+			fringing: option(options.fringing, base.fringing),
+			bleed: option(options.bleed, base.bleed),
+			sharpness: option(options.sharpness, base.sharpness),
+			merge_fields: options.merge_fields.unwrap_or(base.merge_fields),
+		};
+		let crop = self.crop.as_ref().map(|crop| crop.with_source_width(width));
+		let (cropped_width, height) = crop.as_ref().map_or((width, SCREEN_HEIGHT), |crop| (crop.width(), crop.height()));
+		check_scaled_size(cropped_width, height, self.scale_factor())?;
+		if self.ntsc_framebuffer.is_empty() {
+			self.ntsc_framebuffer = vec![0; (MAX_NTSC_WIDTH * SCREEN_HEIGHT * 3) as usize];
 		}
+		self.ntsc_filter = Some(NtscFilter::new(params, width));
+		self.crop = crop;
+		self.refresh_framebuffer();
+		Ok(())
+	}
+
+	/// Keeps the palette indices of each frame for `getPaletteIndices()`
+	#[napi]
+	pub fn set_palette_index_output(&mut self, enabled: bool) {
+		self.palette_index_output = enabled;
+		self.refresh_framebuffer();
+	}
+
+	/// 256x240 view of the last frame before palette lookup, one entry a pixel with
+	/// the 6-bit color in bits 0-5 and the PPUMASK emphasis bits (red, green, blue)
+	/// in bits 6-8. Only updated while `setPaletteIndexOutput(true)` or the NTSC
+	/// filter is on.
+	#[napi]
+	pub fn get_palette_indices(&mut self) -> Uint16Array {
+		let ptr = self.palette_indices.as_mut_ptr();
+		let len = self.palette_indices.len();
+		unsafe { Uint16Array::with_external_data(ptr, len, |_data, _len| {}) }
 	}
 
 	#[napi]
@@ -190,6 +760,111 @@ impl NativeNes {
 		};
 	}
 
+	/// With the limit off every sprite on a scanline is drawn, which removes
+	/// the flicker of games cycling sprites. Overflow and sprite 0 hit are unaffected.
+	#[napi]
+	pub fn set_sprite_limit(&mut self, enabled: bool) {
+		self.nes.set_sprite_limit(enabled);
+		if let Some(instance) = self.run_ahead_instance.as_mut() {
+			instance.set_sprite_limit(enabled);
+		}
+	}
+
+	/// Loads a .pal file, 192 bytes for 64 colors or 1536 bytes with the
+	/// colors for each emphasis combination.
+	#[napi]
+	pub fn set_palette(&mut self, data: Uint8Array) -> Result<()> {
+		let palette = palette::parse_pal(&data).map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+		self.apply_palette(palette)
+	}
+
+	/// Generates the palette from a model of the NTSC signal.
+	#[napi]
+	pub fn generate_palette(&mut self, options: Option<PaletteOptions>) -> Result<()> {
+		let options = options.unwrap_or(PaletteOptions {
+			preset: None,
+			hue: None,
+			saturation: None,
+			contrast: None,
+			brightness: None,
+			gamma: None,
+		});
+		let preset = match options.preset.unwrap_or(0) {
+			0 => PalettePreset::CompositeDirect,
+			1 => PalettePreset::Fceux,
+			2 => PalettePreset::Nestopia,
+			3 => PalettePreset::Smooth,
+			preset => {
+				return Err(Error::new(Status::InvalidArg, format!("Unknown palette preset {}", preset)));
+			}
+		};
+		let base = preset.params();
+		let params = NtscPaletteParams {
+			hue: options.hue.map_or(base.hue, |value| value as f32),
+			saturation: options.saturation.map_or(base.saturation, |value| value as f32),
+			contrast: options.contrast.map_or(base.contrast, |value| value as f32),
+			brightness: options.brightness.map_or(base.brightness, |value| value as f32),
+			gamma: options.gamma.map_or(base.gamma, |value| value as f32),
+		};
+		self.apply_palette(palette::generate_ntsc_palette(&params))
+	}
+
+	/// Goes back to the built-in palette.
+	#[napi]
+	pub fn reset_palette(&mut self) {
+		self.nes.reset_palette();
+		if let Some(instance) = self.run_ahead_instance.as_mut() {
+			instance.reset_palette();
+		}
+	}
+
+	/// Debug switches hiding layers from the picture without affecting
+	/// emulation. Bits as in PPUMASK, set to show: 0x08 background,
+	/// 0x10 sprites. `hidden_sprites` lists OAM indices (0-63) to hide.
+	#[napi]
+	pub fn set_layer_mask(&mut self, bits: u8, hidden_sprites: Option<Vec<u8>>) {
+		let mask = LayerMask {
+			background: bits & 0x08 != 0,
+			sprites: bits & 0x10 != 0,
+			hidden_sprites: hidden_sprites
+				.unwrap_or_default()
+				.iter()
+				.filter(|index| **index < 64)
+				.fold(0, |hidden, index| hidden | (1u64 << index)),
+		};
+		self.nes.set_layer_mask(mask);
+		if let Some(instance) = self.run_ahead_instance.as_mut() {
+			instance.set_layer_mask(mask);
+		}
+	}
+
+	/// 0 follows the rom header, 1 NTSC, 2 PAL, 3 Dendy.
+	#[napi]
+	pub fn set_region(&mut self, mode: u8) {
+		self.region_override = match mode {
+			1 => Some(Region::Ntsc),
+			2 => Some(Region::Pal),
+			3 => Some(Region::Dendy),
+			_ => None,
+		};
+		self.apply_region(self.region());
+	}
+
+	/// Region currently emulated: 1 NTSC, 2 PAL, 3 Dendy.
+	#[napi]
+	pub fn get_region(&self) -> u8 {
+		match self.nes.region() {
+			Region::Ntsc => 1,
+			Region::Pal => 2,
+			Region::Dendy => 3,
+		}
+	}
+
+	#[napi]
+	pub fn get_frame_rate(&self) -> f64 {
+		self.nes.region().frame_rate()
+	}
+
 	#[napi]
 	pub fn set_audio_enabled(&mut self, enabled: bool) -> bool {
 		self.audio_backend.set_enabled(enabled)
@@ -197,6 +872,10 @@ impl NativeNes {
 
 	#[napi]
 	pub fn press_button(&mut self, button: u8) {
+		// Movie playback owns the joypads
+		if self.movie.is_playing() {
+			return;
+		}
 		if let Some(mapped) = map_button(button) {
 			self.nes.press_button(mapped);
 		}
@@ -204,6 +883,10 @@ impl NativeNes {
 
 	#[napi]
 	pub fn release_button(&mut self, button: u8) {
+		// Movie playback owns the joypads
+		if self.movie.is_playing() {
+			return;
+		}
 		if let Some(mapped) = map_button(button) {
 			self.nes.release_button(mapped);
 		}
@@ -234,20 +917,489 @@ impl NativeNes {
 		self.nes.mark_sram_saved();
 	}
 
+	#[napi]
+	pub fn save_state(&self) -> Uint8Array {
+		Uint8Array::from(self.nes.save_state())
+	}
+
+	#[napi]
+	pub fn load_state(&mut self, data: Uint8Array) -> Result<()> {
+		self.nes.load_state(&data).map_err(|err| Error::new(Status::InvalidArg, err.to_string()))?;
+		self.break_hit = None;
+		Ok(())
+	}
+
+	/// Starts recording joypad input, anchored at power-on (the rom is
+	/// reloaded and SRAM cleared) or at the current machine state.
+	#[napi]
+	pub fn start_movie_recording(&mut self, from_power_on: bool, checkpoint_interval: Option<u32>) {
+		let anchor = match from_power_on {
+			true => {
+				self.power_on();
+				MovieAnchor::PowerOn
+			}
+			false => MovieAnchor::SaveState(self.nes.save_state()),
+		};
+		let mut movie = Movie::new(anchor);
+		movie.set_pal(self.nes.region() == Region::Pal);
+		self.rewind.clear();
+		self.movie
+			.start_recording(movie, checkpoint_interval.unwrap_or(DEFAULT_CHECKPOINT_INTERVAL));
+	}
+
+	/// Restores the movie's anchor and replays its input from the next frame.
+	#[napi]
+	pub fn start_movie_playback(&mut self, fm2: String) -> Result<()> {
+		let movie = Movie::from_fm2(&fm2).map_err(|err| Error::new(Status::InvalidArg, err.to_string()))?;
+		// The movie's palFlag wins over the configured region until it stops
+		let region = match (movie.is_pal(), self.region()) {
+			(true, _) => Region::Pal,
+			(false, Region::Pal) => Region::Ntsc,
+			(false, region) => region,
+		};
+		self.apply_region(region);
+		match &movie.anchor {
+			MovieAnchor::PowerOn => self.power_on(),
+			MovieAnchor::SaveState(state) => {
+				self.nes.load_state(state).map_err(|err| Error::new(Status::InvalidArg, err.to_string()))?
+			}
+		}
+		self.break_hit = None;
+		self.nes.clear_inputs();
+		self.rewind.clear();
+		self.movie.start_playback(movie);
+		Ok(())
+	}
+
+	#[napi]
+	pub fn stop_movie(&mut self) {
+		self.movie.stop();
+		self.apply_region(self.region());
+	}
+
+	/// Exports the last recorded or loaded movie as FM2 text.
+	#[napi]
+	pub fn export_movie(&self, rom_filename: Option<String>) -> Result<String> {
+		self.movie
+			.movie()
+			.map(|movie| movie.to_fm2(rom_filename.as_deref()))
+			.ok_or_else(|| Error::new(Status::GenericFailure, "No movie has been recorded or loaded"))
+	}
+
+	#[napi]
+	pub fn get_movie_status(&self) -> MovieStatus {
+		MovieStatus {
+			mode: self.movie.mode().name().to_string(),
+			frame: self.movie.frame(),
+			length: self.movie.length(),
+			desync_frame: self.movie.desync_frame(),
+		}
+	}
+
+	/// Presents the frame `frames` frames ahead of the real machine to hide
+	/// the game's internal input lag. Zero disables run-ahead.
+	/// `second_instance` runs the speculative frames on a separate machine.
+	#[napi]
+	pub fn set_run_ahead(&mut self, frames: u32, second_instance: Option<bool>) {
+		self.run_ahead_frames = frames.min(MAX_RUN_AHEAD_FRAMES);
+		self.run_ahead_instance = match second_instance.unwrap_or(false) && frames > 0 {
+			true => Some(create_run_ahead_instance(&self.rom_data, &self.nes)),
+			false => None,
+		};
+		self.sync_run_ahead_cheats();
+	}
+
+	/// Enables the rewind history. A zero budget disables it.
+	/// Omitted arguments fall back to 8MB and a snapshot every frame.
+	#[napi]
+	pub fn set_rewind(&mut self, budget_bytes: Option<u32>, frame_interval: Option<u32>) {
+		self.rewind.configure(
+			budget_bytes.map_or(DEFAULT_REWIND_BUDGET_BYTES, |bytes| bytes as usize),
+			frame_interval.unwrap_or(DEFAULT_REWIND_INTERVAL),
+		);
+	}
+
+	#[napi]
+	pub fn get_rewind_frames(&self) -> u32 {
+		self.rewind.available_frames()
+	}
+
+	/// Steps the machine back by at least `frames` frames, as far as the
+	/// history allows. Returns the number of frames actually rewound.
+	#[napi]
+	pub fn rewind(&mut self, frames: u32) -> Result<u32> {
+		let Some((state, rewound)) = self.rewind.rewind(frames) else {
+			return Ok(0);
+		};
+		self.nes.load_state(state).map_err(state_error)?;
+		self.break_hit = None;
+		Ok(rewound)
+	}
+
+	/// Starts a nestest.log style CPU trace, replacing any running one.
+	/// Lines accumulate until drained with `take_trace()`; tracing stops
+	/// by itself if 16MB pile up.
+	#[napi]
+	pub fn start_trace(&mut self, options: Option<TraceOptions>) {
+		let filter = options.map_or_else(TraceFilter::default, |options| TraceFilter {
+			frames: match (options.start_frame, options.end_frame) {
+				(None, None) => None,
+				(start, end) => Some(start.unwrap_or(0)..end.unwrap_or(u32::MAX)),
+			},
+			pc: match (options.start_pc, options.end_pc) {
+				(None, None) => None,
+				(start, end) => Some(start.unwrap_or(0)..=end.unwrap_or(0xFFFF)),
+			},
+		});
+		let buffer = TraceBuffer::new(DEFAULT_TRACE_BUFFER_BYTES);
+		self.nes.start_trace(Tracer::new(Box::new(buffer.clone()), filter));
+		self.trace_buffer = Some(buffer);
+	}
+
+	/// Stops tracing. Lines not taken yet stay available to `take_trace()`.
+	#[napi]
+	pub fn stop_trace(&mut self) {
+		self.nes.stop_trace();
+	}
+
+	#[napi]
+	pub fn is_tracing(&self) -> bool {
+		self.nes.tracer().is_some_and(|tracer| tracer.error().is_none())
+	}
+
+	/// Returns and clears the trace lines logged so far.
+	#[napi]
+	pub fn take_trace(&mut self) -> String {
+		let Some(buffer) = self.trace_buffer.as_ref() else {
+			return String::new();
+		};
+		let bytes = buffer.take();
+		if !self.is_tracing() {
+			self.trace_buffer = None;
+		}
+		String::from_utf8(bytes).unwrap_or_default()
+	}
+
+	/// Reads a byte without side effects. Domains: 0 CPU bus, 1 internal RAM,
+	/// 2 PRG-ROM, 3 PRG-RAM, 4 CHR, 5 nametables, 6 palette, 7 OAM.
+	/// Returns null past the end of the domain.
+	#[napi]
+	pub fn peek(&self, domain: u8, address: u32) -> Result<Option<u8>> {
+		Ok(self.nes.peek(map_memory_domain(domain)?, address as usize))
+	}
+
+	/// Writes a byte without side effects. Returns false past the end of the domain.
+	#[napi]
+	pub fn poke(&mut self, domain: u8, address: u32, value: u8) -> Result<bool> {
+		Ok(self.nes.poke(map_memory_domain(domain)?, address as usize, value))
+	}
+
+	/// Copies up to `length` bytes from `address`, stopping at the end of the domain.
+	#[napi]
+	pub fn read_range(&self, domain: u8, address: u32, length: u32) -> Result<Uint8Array> {
+		let domain = map_memory_domain(domain)?;
+		let end = (address as usize)
+			.saturating_add(length as usize)
+			.min(self.nes.memory_domain_size(domain));
+		let bytes: Vec<u8> = (address as usize..end)
+			.map(|offset| self.nes.peek(domain, offset).unwrap_or(0))
+			.collect();
+		Ok(Uint8Array::new(bytes))
+	}
+
+	#[napi]
+	pub fn get_memory_domain_size(&self, domain: u8) -> Result<u32> {
+		Ok(self.nes.memory_domain_size(map_memory_domain(domain)?) as u32)
+	}
+
+	/// Adds a breakpoint or watchpoint and returns its id.
+	#[napi]
+	pub fn add_breakpoint(&mut self, options: BreakpointOptions) -> Result<u32> {
+		let kind = match options.kind {
+			0 => BreakpointKind::Execute,
+			1 => BreakpointKind::Read,
+			2 => BreakpointKind::Write,
+			3 => BreakpointKind::VramWrite,
+			kind => return Err(Error::new(Status::InvalidArg, format!("Unknown breakpoint kind {}", kind))),
+		};
+		let condition = match options.condition.as_deref().map(str::trim) {
+			None | Some("") => None,
+			Some(source) => Some(
+				Expression::parse(source)
+					.map_err(|err| Error::new(Status::InvalidArg, format!("Invalid condition: {}", err)))?,
+			),
+		};
+		let mut breakpoint = Breakpoint::new(kind, options.address);
+		breakpoint.addresses = options.address..=options.end_address.unwrap_or(options.address).max(options.address);
+		breakpoint.condition = condition;
+		breakpoint.min_hits = options.min_hits.unwrap_or(0);
+		Ok(self.nes.add_breakpoint(breakpoint))
+	}
+
+	#[napi]
+	pub fn remove_breakpoint(&mut self, id: u32) -> bool {
+		self.nes.remove_breakpoint(id)
+	}
+
+	#[napi]
+	pub fn clear_breakpoints(&mut self) {
+		self.nes.clear_breakpoints();
+	}
+
+	#[napi]
+	pub fn get_breakpoints(&self) -> Vec<BreakpointInfo> {
+		self.nes
+			.breakpoints()
+			.iter()
+			.map(|entry| BreakpointInfo {
+				id: entry.id,
+				kind: breakpoint_kind_code(entry.breakpoint.kind),
+				address: *entry.breakpoint.addresses.start(),
+				end_address: *entry.breakpoint.addresses.end(),
+				condition: entry.breakpoint.condition.as_ref().map(|condition| condition.source().to_string()),
+				min_hits: entry.breakpoint.min_hits,
+				hits: entry.hits,
+			})
+			.collect()
+	}
+
+	/// The breakpoint which stopped the last `step_frame()` mid-frame, if any.
+	#[napi]
+	pub fn get_break_hit(&self) -> Option<BreakpointHit> {
+		self.break_hit.as_ref().map(|hit| BreakpointHit {
+			id: hit.id,
+			kind: breakpoint_kind_code(hit.kind),
+			address: hit.address,
+			value: hit.value,
+			cpu: cpu_debug_state(&hit.cpu),
+		})
+	}
+
+	/// Adds a Game Genie (SXIOPO, YEUZUGAA), Pro Action Replay (00075A09)
+	/// or raw (075A:09, C123?20:EA) code and returns its id. ROM patches
+	/// apply to reads from $8000-$FFFF, lower addresses are frozen every frame.
+	#[napi]
+	pub fn add_cheat(&mut self, code: String) -> Result<u32> {
+		let id = self
+			.nes
+			.add_cheat(&code)
+			.map_err(|err| Error::new(Status::InvalidArg, err.to_string()))?;
+		self.sync_run_ahead_cheats();
+		Ok(id)
+	}
+
+	#[napi]
+	pub fn remove_cheat(&mut self, id: u32) -> bool {
+		let removed = self.nes.remove_cheat(id);
+		self.sync_run_ahead_cheats();
+		removed
+	}
+
+	#[napi]
+	pub fn set_cheat_enabled(&mut self, id: u32, enabled: bool) -> bool {
+		let found = self.nes.set_cheat_enabled(id, enabled);
+		self.sync_run_ahead_cheats();
+		found
+	}
+
+	#[napi]
+	pub fn list_cheats(&self) -> Vec<CheatInfo> {
+		self.nes
+			.cheats()
+			.iter()
+			.map(|entry| CheatInfo {
+				id: entry.id,
+				code: entry.code.clone(),
+				format: match entry.format {
+					CheatFormat::GameGenie => "game-genie",
+					CheatFormat::ProActionReplay => "pro-action-replay",
+					CheatFormat::Raw => "raw",
+				}
+				.to_string(),
+				address: entry.cheat.address,
+				value: entry.cheat.value,
+				compare: entry.cheat.compare,
+				enabled: entry.enabled,
+			})
+			.collect()
+	}
+
+	/// Starts a RAM search over internal RAM and PRG-RAM with every address
+	/// as a candidate and returns the candidate count.
+	/// Widths: 1 or 2 bytes (little endian). Encodings: 0 unsigned, 1 signed, 2 BCD.
+	#[napi]
+	pub fn start_ram_search(&mut self, width: u8, encoding: u8) -> Result<u32> {
+		let width = match width {
+			1 => SearchWidth::Byte,
+			2 => SearchWidth::Word,
+			width => return Err(Error::new(Status::InvalidArg, format!("Unsupported search width {}", width))),
+		};
+		let encoding = match encoding {
+			0 => SearchEncoding::Unsigned,
+			1 => SearchEncoding::Signed,
+			2 => SearchEncoding::Bcd,
+			encoding => return Err(Error::new(Status::InvalidArg, format!("Unknown search encoding {}", encoding))),
+		};
+		let mut search = RamSearch::new(width, encoding);
+		search.reset(&self.nes.search_ram());
+		let count = search.candidate_count() as u32;
+		self.ram_search = Some(search);
+		Ok(count)
+	}
+
+	/// Snapshots RAM and keeps the candidates passing the comparison against
+	/// the previous snapshot. Returns the remaining candidate count.
+	/// Comparisons: 0 equal, 1 not equal, 2 greater, 3 less,
+	/// 4 changed by `operand`, 5 equal to `operand`.
+	#[napi]
+	pub fn filter_ram_search(&mut self, comparison: u8, operand: Option<i64>) -> Result<u32> {
+		let search = self
+			.ram_search
+			.as_mut()
+			.ok_or_else(|| Error::new(Status::GenericFailure, "No RAM search in progress"))?;
+		let operand = || operand.ok_or_else(|| Error::new(Status::InvalidArg, "Comparison needs an operand"));
+		let comparison = match comparison {
+			0 => SearchComparison::Equal,
+			1 => SearchComparison::NotEqual,
+			2 => SearchComparison::Greater,
+			3 => SearchComparison::Less,
+			4 => SearchComparison::ChangedBy(operand()?),
+			5 => SearchComparison::EqualTo(operand()?),
+			comparison => return Err(Error::new(Status::InvalidArg, format!("Unknown comparison {}", comparison))),
+		};
+		Ok(search.filter(comparison, &self.nes.search_ram()) as u32)
+	}
+
+	/// Remaining candidates from the lowest address, at most `limit` (4096 by default).
+	#[napi]
+	pub fn get_ram_search_results(&self, limit: Option<u32>) -> Vec<RamSearchResult> {
+		let limit = limit.unwrap_or(MAX_RAM_SEARCH_RESULTS).min(MAX_RAM_SEARCH_RESULTS) as usize;
+		self.ram_search.as_ref().map_or_else(Vec::new, |search| {
+			search
+				.results(limit)
+				.into_iter()
+				.map(|result| RamSearchResult {
+					address: result.address,
+					value: result.value,
+					previous: result.previous,
+				})
+				.collect()
+		})
+	}
+
+	#[napi]
+	pub fn get_ram_search_count(&self) -> u32 {
+		self.ram_search.as_ref().map_or(0, |search| search.candidate_count() as u32)
+	}
+
+	#[napi]
+	pub fn stop_ram_search(&mut self) {
+		self.ram_search = None;
+	}
+
+	/// Renders pattern table 0 or 1 with palette 0-3 (background) or 4-7 (sprites)
+	/// into `buffer`, 128x128 RGB.
+	#[napi]
+	pub fn render_pattern_table(&self, table: u8, palette: u8, mut buffer: Uint8Array) -> Result<()> {
+		check_image_buffer(&buffer, PATTERN_TABLE_WIDTH, PATTERN_TABLE_HEIGHT)?;
+		self.nes.render_pattern_table(table, palette, &mut buffer);
+		Ok(())
+	}
+
+	/// Renders the four nametables into `buffer`, 512x480 RGB, optionally
+	/// outlining the scroll window.
+	#[napi]
+	pub fn render_name_tables(&self, mut buffer: Uint8Array, scroll_overlay: Option<bool>) -> Result<()> {
+		check_image_buffer(&buffer, NAME_TABLES_WIDTH, NAME_TABLES_HEIGHT)?;
+		self.nes.render_name_tables(scroll_overlay.unwrap_or(true), &mut buffer);
+		Ok(())
+	}
+
+	/// Renders the 32 palette entries as 8x8 swatches into `buffer`, 128x16 RGB.
+	/// Background palettes are on the top row.
+	#[napi]
+	pub fn render_palettes(&self, mut buffer: Uint8Array) -> Result<()> {
+		check_image_buffer(&buffer, PALETTE_VIEW_WIDTH, PALETTE_VIEW_HEIGHT)?;
+		self.nes.render_palettes(&mut buffer);
+		Ok(())
+	}
+
+	#[napi]
+	pub fn get_oam_sprites(&self) -> Vec<OamSpriteInfo> {
+		(0..64)
+			.map(|index| {
+				let sprite = self.nes.oam_sprite(index);
+				OamSpriteInfo {
+					index: sprite.index,
+					x: sprite.x,
+					y: sprite.y,
+					tile: sprite.tile,
+					attributes: sprite.attributes,
+					palette: sprite.palette,
+					behind_background: sprite.behind_background,
+					flip_horizontal: sprite.flip_horizontal,
+					flip_vertical: sprite.flip_vertical,
+					height: sprite.height,
+				}
+			})
+			.collect()
+	}
+
+	/// Renders 8x16 thumbnails of the 64 OAM sprites into `buffer`,
+	/// 64x128 RGB with eight sprites a row.
+	#[napi]
+	pub fn render_sprites(&self, mut buffer: Uint8Array) -> Result<()> {
+		let atlas_width = SPRITE_THUMBNAIL_WIDTH * SPRITE_ATLAS_COLUMNS;
+		check_image_buffer(&buffer, atlas_width, SPRITE_THUMBNAIL_HEIGHT * 64 / SPRITE_ATLAS_COLUMNS)?;
+		let row_len = (SPRITE_THUMBNAIL_WIDTH * 3) as usize;
+		let mut thumbnail = vec![0; SPRITE_THUMBNAIL_BYTE_LEN];
+		for index in 0..64u32 {
+			self.nes.render_sprite(index as u8, &mut thumbnail);
+			let origin_x = (index % SPRITE_ATLAS_COLUMNS) * SPRITE_THUMBNAIL_WIDTH;
+			let origin_y = (index / SPRITE_ATLAS_COLUMNS) * SPRITE_THUMBNAIL_HEIGHT;
+			for (y, row) in thumbnail.chunks(row_len).enumerate() {
+				let offset = (((origin_y + y as u32) * atlas_width + origin_x) * 3) as usize;
+				buffer[offset..offset + row_len].copy_from_slice(row);
+			}
+		}
+		Ok(())
+	}
+
+	/// Disassembles up to `count` instructions from `address` on the CPU bus.
+	/// With `prg_offset` it reads PRG-ROM from that offset instead, as if
+	/// mapped at `address`, regardless of the current banking.
+	#[napi]
+	pub fn disassemble(&self, address: u16, count: u32, prg_offset: Option<u32>) -> Vec<DisassembledInstruction> {
+		let count = count.min(MAX_DISASSEMBLY_COUNT) as usize;
+		let instructions = match prg_offset {
+			Some(offset) => self.nes.disassemble_prg(offset as usize, address, count),
+			None => self.nes.disassemble(address, count),
+		};
+		instructions
+			.into_iter()
+			.map(|instruction| DisassembledInstruction {
+				address: instruction.address,
+				bytes: instruction.bytes,
+				mnemonic: instruction.mnemonic,
+				operand: instruction.operand,
+				effective_address: instruction.effective_address,
+				length: instruction.length,
+				unofficial: instruction.unofficial,
+			})
+			.collect()
+	}
+
+	/// PPU frames since bootup, as used by the trace frame range.
+	#[napi]
+	pub fn get_frame(&self) -> u32 {
+		self.nes.frame()
+	}
+
 	#[napi]
 	pub fn get_debug_state(&self) -> NesDebugState {
 		let state = self.nes.debug_state();
 		NesDebugState {
-			cpu: CpuDebugState {
-				pc: state.cpu.pc,
-				a: state.cpu.a,
-				x: state.cpu.x,
-				y: state.cpu.y,
-				sp: state.cpu.sp,
-				p: state.cpu.p,
-				last_pc: state.cpu.last_pc,
-				last_opcode: state.cpu.last_opcode,
-			},
+			cpu: cpu_debug_state(&state.cpu),
 			mapper: MapperDebugState {
 				mapper_num: state.mapper.mapper_num,
 				control: state.mapper.control,
@@ -261,11 +1413,160 @@ impl NativeNes {
 		}
 	}
 
+	/// RGB frames of `getFramebufferSize()`, updated in place by `refreshFramebuffer()`
 	#[napi]
 	pub fn get_framebuffer(&mut self) -> Uint8Array {
-		let ptr = self.framebuffer.as_mut_ptr();
-		let len = self.framebuffer.len();
-		unsafe { Uint8Array::with_external_data(ptr, len, |_data, _len| {}) }
+		let frame = self.output_frame();
+		unsafe { Uint8Array::with_external_data(frame.as_mut_ptr(), frame.len(), |_data, _len| {}) }
+	}
+}
+
+impl NativeNes {
+	// Width of the frames going into the crop, the NTSC filter's or the framebuffer's
+	fn picture_width(&self) -> u32 {
+		self.ntsc_filter.as_ref().map_or(SCREEN_WIDTH, |filter| filter.width())
+	}
+
+	// Size of the frames going into the upscaler
+	fn cropped_size(&self) -> (u32, u32) {
+		match self.crop.as_ref() {
+			Some(crop) => (crop.width(), crop.height()),
+			None => (self.picture_width(), SCREEN_HEIGHT),
+		}
+	}
+
+	fn scale_factor(&self) -> u32 {
+		self.upscaler.as_ref().map_or(1, |upscaler| upscaler.factor())
+	}
+
+	// The last stage of the framebuffer pipeline
+	fn output_frame(&mut self) -> &mut [u8] {
+		let size = self.get_framebuffer_size();
+		let len = (size.width * size.height * 3) as usize;
+		match (self.upscaler.is_some(), self.crop.is_some(), self.ntsc_filter.is_some()) {
+			(true, _, _) => &mut self.scaled_framebuffer[..len],
+			(false, true, _) => &mut self.cropped_framebuffer[..len],
+			(false, false, true) => &mut self.ntsc_framebuffer[..len],
+			(false, false, false) => &mut self.framebuffer[..len],
+		}
+	}
+
+	// Deterministic power-on, see `movie::power_on()`
+	fn power_on(&mut self) {
+		movie::power_on(&mut self.nes, &self.rom_data);
+		self.break_hit = None;
+	}
+
+	// The second instance has to see the same patched game
+	fn sync_run_ahead_cheats(&mut self) {
+		if let Some(instance) = self.run_ahead_instance.as_mut() {
+			instance.clear_cheats();
+			for entry in self.nes.cheats() {
+				if entry.enabled {
+					let _ = instance.add_cheat(&entry.code);
+				}
+			}
+		}
+	}
+
+	fn region(&self) -> Region {
+		self.region_override.unwrap_or(self.header_region)
+	}
+
+	fn apply_palette(&mut self, palette: Vec<u32>) -> Result<()> {
+		let to_error = |e: PaletteError| Error::new(Status::InvalidArg, e.to_string());
+		if let Some(instance) = self.run_ahead_instance.as_mut() {
+			instance.set_palette(palette.clone()).map_err(to_error)?;
+		}
+		self.nes.set_palette(palette).map_err(to_error)
+	}
+
+	fn apply_region(&mut self, region: Region) {
+		self.nes.set_region(region);
+		if let Some(instance) = self.run_ahead_instance.as_mut() {
+			instance.set_region(region);
+		}
+	}
+}
+
+// A second machine with the same rom and output settings as `source`
+fn create_run_ahead_instance(rom_data: &[u8], source: &Nes) -> Nes {
+	let input = Box::new(DefaultInput::new());
+	let display = Box::new(NativeDisplay::new());
+	let audio = Box::new(DefaultAudio::new());
+	let mut nes = Nes::new(input, display, audio);
+	if !rom_data.is_empty() {
+		nes.set_rom(Rom::new(rom_data.to_vec()));
+	}
+	nes.set_audio_output_enabled(false);
+	nes.set_region(source.region());
+	// It presents the frames in second-instance mode
+	nes.set_layer_mask(source.layer_mask());
+	nes.set_sprite_limit(source.sprite_limit());
+	// Palettes of a running instance always have all 512 colors
+	let _ = nes.set_palette(source.palette().to_vec());
+	nes
+}
+
+fn check_scaled_size(width: u32, height: u32, factor: u32) -> Result<()> {
+	match (width * height * 3 * factor * factor) as usize <= MAX_SCALED_FRAME_BYTE_LEN {
+		true => Ok(()),
+		false => Err(Error::new(
+			Status::InvalidArg,
+			format!("{}x{} frames upscaled {} times are too large", width, height, factor),
+		)),
+	}
+}
+
+fn check_image_buffer(buffer: &[u8], width: u32, height: u32) -> Result<()> {
+	let expected = (width * height * 3) as usize;
+	match buffer.len() == expected {
+		true => Ok(()),
+		false => Err(Error::new(
+			Status::InvalidArg,
+			format!("Expected a {}x{} RGB buffer of {} bytes, got {}", width, height, expected, buffer.len()),
+		)),
+	}
+}
+
+fn state_error(err: StateError) -> Error {
+	Error::new(Status::GenericFailure, err.to_string())
+}
+
+fn cpu_debug_state(state: &nes_rust::cpu::CpuDebugState) -> CpuDebugState {
+	CpuDebugState {
+		pc: state.pc,
+		a: state.a,
+		x: state.x,
+		y: state.y,
+		sp: state.sp,
+		p: state.p,
+		last_pc: state.last_pc,
+		last_opcode: state.last_opcode,
+		jammed: state.jammed,
+	}
+}
+
+fn breakpoint_kind_code(kind: BreakpointKind) -> u8 {
+	match kind {
+		BreakpointKind::Execute => 0,
+		BreakpointKind::Read => 1,
+		BreakpointKind::Write => 2,
+		BreakpointKind::VramWrite => 3,
+	}
+}
+
+fn map_memory_domain(domain: u8) -> Result<MemoryDomain> {
+	match domain {
+		0 => Ok(MemoryDomain::CpuBus),
+		1 => Ok(MemoryDomain::InternalRam),
+		2 => Ok(MemoryDomain::PrgRom),
+		3 => Ok(MemoryDomain::PrgRam),
+		4 => Ok(MemoryDomain::Chr),
+		5 => Ok(MemoryDomain::Nametable),
+		6 => Ok(MemoryDomain::Palette),
+		7 => Ok(MemoryDomain::Oam),
+		_ => Err(Error::new(Status::InvalidArg, format!("Unknown memory domain {}", domain))),
 	}
 }
 
@@ -358,5 +1659,5 @@ fn yiq_to_rgb(y: f32, i: f32, q: f32) -> (f32, f32, f32) {
 }
 
 fn clamp_u8(value: f32) -> u8 {
-	value.max(0.0).min(255.0).round() as u8
+	value.clamp(0.0, 255.0).round() as u8
 }
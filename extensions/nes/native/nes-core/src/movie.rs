@@ -0,0 +1,645 @@
+use std::fmt;
+
+use nes_rust::default_audio::DefaultAudio;
+use nes_rust::default_display::DefaultDisplay;
+use nes_rust::default_input::DefaultInput;
+use nes_rust::rom::Rom;
+use nes_rust::state::checksum;
+use nes_rust::Nes;
+
+pub const DEFAULT_CHECKPOINT_INTERVAL: u32 = 60;
+
+pub const COMMAND_SOFT_RESET: u8 = 0x01;
+pub const COMMAND_POWER: u8 = 0x02;
+
+// FM2 writes each pad as "RLDUTSBA", most significant bit first. The bit
+// order matches `Nes::joypad_state`.
+const FM2_BUTTONS: &[u8; 8] = b"RLDUTSBA";
+const FM2_VERSION: &str = "3";
+const CHECKPOINT_PREFIX: &str = "checkpoint ";
+const SAVESTATE_PREFIX: &str = "base64:";
+
+// Written on export unless the imported movie carried its own value.
+const FM2_DEFAULT_HEADER: [(&str, &str); 7] = [
+	("emuVersion", "22020"),
+	("rerecordCount", "0"),
+	("palFlag", "0"),
+	("fourscore", "0"),
+	("microphone", "0"),
+	("FDS", "0"),
+	("NewPPU", "0"),
+];
+
+// Regenerated on export from the movie contents.
+const FM2_GENERATED_KEYS: [&str; 5] = ["version", "port0", "port1", "port2", "savestate"];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MovieFrame {
+	pub commands: u8,
+	pub ports: [u8; 2],
+}
+
+pub enum MovieAnchor {
+	PowerOn,
+	SaveState(Vec<u8>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+	pub frame: u32,
+	pub hash: u32,
+}
+
+pub struct Movie {
+	pub anchor: MovieAnchor,
+	pub frames: Vec<MovieFrame>,
+	pub checkpoints: Vec<Checkpoint>,
+	// Header lines that aren't interpreted, kept as-is for export
+	pub header: Vec<(String, String)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MovieError {
+	UnsupportedVersion(String),
+	Malformed { line: usize, reason: &'static str },
+}
+
+impl fmt::Display for MovieError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			MovieError::UnsupportedVersion(version) => write!(f, "unsupported FM2 version {}", version),
+			MovieError::Malformed { line, reason } => write!(f, "malformed FM2 at line {}: {}", line, reason),
+		}
+	}
+}
+
+impl Movie {
+	pub fn new(anchor: MovieAnchor) -> Self {
+		Self {
+			anchor,
+			frames: Vec::new(),
+			checkpoints: Vec::new(),
+			header: Vec::new(),
+		}
+	}
+
+	fn checkpoint(&self, frame: u32) -> Option<u32> {
+		self.checkpoints
+			.binary_search_by_key(&frame, |checkpoint| checkpoint.frame)
+			.ok()
+			.map(|index| self.checkpoints[index].hash)
+	}
+
+	/// Parses an FCEUX `.fm2` movie. Checkpoints are read back from
+	/// `comment checkpoint <frame> <hash>` lines written by `to_fm2`.
+	pub fn from_fm2(text: &str) -> Result<Self, MovieError> {
+		let mut movie = Movie::new(MovieAnchor::PowerOn);
+		let mut version = None;
+		for (index, line) in text.lines().enumerate() {
+			let line_number = index + 1;
+			let line = line.trim_end_matches('\r');
+			if line.is_empty() {
+				continue;
+			}
+			if line.starts_with('|') {
+				movie.frames.push(parse_input_line(line, line_number)?);
+				continue;
+			}
+			let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+			match key {
+				"version" => version = Some(value.to_string()),
+				"port0" | "port1" | "port2" => {}
+				"savestate" => {
+					let encoded = value.strip_prefix(SAVESTATE_PREFIX).ok_or(MovieError::Malformed {
+						line: line_number,
+						reason: "savestate is not base64",
+					})?;
+					let state = base64_decode(encoded).ok_or(MovieError::Malformed {
+						line: line_number,
+						reason: "savestate is not base64",
+					})?;
+					movie.anchor = MovieAnchor::SaveState(state);
+				}
+				"comment" if value.starts_with(CHECKPOINT_PREFIX) => {
+					movie.checkpoints.push(parse_checkpoint(&value[CHECKPOINT_PREFIX.len()..], line_number)?);
+				}
+				_ => movie.header.push((key.to_string(), value.to_string())),
+			}
+		}
+		match version.as_deref() {
+			Some(FM2_VERSION) => {}
+			Some(other) => return Err(MovieError::UnsupportedVersion(other.to_string())),
+			None => return Err(MovieError::UnsupportedVersion(String::new())),
+		}
+		movie.checkpoints.sort_by_key(|checkpoint| checkpoint.frame);
+		Ok(movie)
+	}
+
+	/// Serializes to `.fm2`. Movies anchored at a save state carry it in the
+	/// `savestate` header, which only this core can load back.
+	pub fn to_fm2(&self, rom_filename: Option<&str>) -> String {
+		let mut out = String::new();
+		let mut push_line = |key: &str, value: &str| {
+			out.push_str(key);
+			out.push(' ');
+			out.push_str(value);
+			out.push('\n');
+		};
+		push_line("version", FM2_VERSION);
+		for (key, value) in FM2_DEFAULT_HEADER.iter() {
+			if !self.has_header(key) {
+				push_line(key, value);
+			}
+		}
+		if let Some(name) = rom_filename {
+			if !self.has_header("romFilename") {
+				push_line("romFilename", name);
+			}
+		}
+		for (key, value) in &self.header {
+			if !FM2_GENERATED_KEYS.contains(&key.as_str()) {
+				push_line(key, value);
+			}
+		}
+		let uses_port1 = self.frames.iter().any(|frame| frame.ports[1] != 0);
+		push_line("port0", "1");
+		push_line("port1", if uses_port1 { "1" } else { "0" });
+		push_line("port2", "0");
+		for checkpoint in &self.checkpoints {
+			push_line(
+				"comment",
+				&format!("{}{} {:08x}", CHECKPOINT_PREFIX, checkpoint.frame, checkpoint.hash),
+			);
+		}
+		if let MovieAnchor::SaveState(state) = &self.anchor {
+			push_line("savestate", &format!("{}{}", SAVESTATE_PREFIX, base64_encode(state)));
+		}
+		for frame in &self.frames {
+			out.push('|');
+			out.push_str(&frame.commands.to_string());
+			out.push('|');
+			write_port(&mut out, frame.ports[0]);
+			out.push('|');
+			if uses_port1 {
+				write_port(&mut out, frame.ports[1]);
+			}
+			out.push_str("||\n");
+		}
+		out
+	}
+
+	/// FM2 `palFlag`. FCEUX has no Dendy flag so those movies record as NTSC.
+	pub fn is_pal(&self) -> bool {
+		self.header.iter().any(|(key, value)| key == "palFlag" && value == "1")
+	}
+
+	pub fn set_pal(&mut self, pal: bool) {
+		self.header.retain(|(key, _)| key != "palFlag");
+		self.header.push(("palFlag".to_string(), (pal as u8).to_string()));
+	}
+
+	fn has_header(&self, key: &str) -> bool {
+		self.header.iter().any(|(existing, _)| existing == key)
+	}
+}
+
+fn parse_input_line(line: &str, line_number: usize) -> Result<MovieFrame, MovieError> {
+	let mut fields = line[1..].split('|');
+	let commands = fields.next().unwrap_or("").trim();
+	let commands = match commands.is_empty() {
+		true => 0,
+		false => commands.parse::<u8>().map_err(|_| MovieError::Malformed {
+			line: line_number,
+			reason: "invalid command field",
+		})?,
+	};
+	let mut ports = [0; 2];
+	for port in ports.iter_mut() {
+		let field = fields.next().unwrap_or("");
+		if field.is_empty() {
+			continue;
+		}
+		if field.len() != FM2_BUTTONS.len() {
+			return Err(MovieError::Malformed {
+				line: line_number,
+				reason: "gamepad field must have 8 buttons",
+			});
+		}
+		for (i, c) in field.bytes().enumerate() {
+			if c != b'.' && c != b' ' {
+				*port |= 0x80 >> i;
+			}
+		}
+	}
+	Ok(MovieFrame { commands, ports })
+}
+
+fn parse_checkpoint(value: &str, line_number: usize) -> Result<Checkpoint, MovieError> {
+	let malformed = || MovieError::Malformed {
+		line: line_number,
+		reason: "checkpoint must be `<frame> <hash>`",
+	};
+	let (frame, hash) = value.split_once(' ').ok_or_else(malformed)?;
+	Ok(Checkpoint {
+		frame: frame.parse().map_err(|_| malformed())?,
+		hash: u32::from_str_radix(hash.trim(), 16).map_err(|_| malformed())?,
+	})
+}
+
+fn write_port(out: &mut String, state: u8) {
+	for (i, c) in FM2_BUTTONS.iter().enumerate() {
+		out.push(if state & (0x80 >> i) != 0 { *c as char } else { '.' });
+	}
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+	let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+	for chunk in data.chunks(3) {
+		let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+		let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+		for i in 0..4 {
+			match i <= chunk.len() {
+				true => out.push(BASE64_ALPHABET[((n >> (18 - i * 6)) & 0x3f) as usize] as char),
+				false => out.push('='),
+			}
+		}
+	}
+	out
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+	let text = text.trim().trim_end_matches('=');
+	let mut out = Vec::with_capacity(text.len() * 3 / 4);
+	let mut bits = 0u32;
+	let mut bit_count = 0;
+	for c in text.bytes() {
+		let value = BASE64_ALPHABET.iter().position(|&a| a == c)? as u32;
+		bits = (bits << 6) | value;
+		bit_count += 6;
+		if bit_count >= 8 {
+			bit_count -= 8;
+			out.push((bits >> bit_count) as u8);
+		}
+	}
+	Some(out)
+}
+
+/// Hash of the whole machine state, compared at checkpoints.
+pub fn frame_hash(nes: &Nes) -> u32 {
+	checksum(&nes.save_state())
+}
+
+/// Power cycle for movies: `nes` takes the state of a new machine booted
+/// with `rom_data`, so RAM, SRAM, PPU and APU don't carry over and every
+/// power-on hashes the same. Output settings, cheats and breakpoints stay.
+pub fn power_on(nes: &mut Nes, rom_data: &[u8]) {
+	let mut fresh = Nes::new(
+		Box::new(DefaultInput::new()),
+		Box::new(DefaultDisplay::new()),
+		Box::new(DefaultAudio::new()),
+	);
+	fresh.set_rom(Rom::new(rom_data.to_vec()));
+	fresh.set_region(nes.region());
+	fresh.bootup();
+	nes.set_rom(Rom::new(rom_data.to_vec()));
+	// Can't fail, the state comes from the same rom
+	let _ = nes.load_state(&fresh.save_state());
+	nes.clear_inputs();
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MovieMode {
+	Idle,
+	Recording,
+	Playing,
+	Finished,
+}
+
+impl MovieMode {
+	pub fn name(self) -> &'static str {
+		match self {
+			MovieMode::Idle => "idle",
+			MovieMode::Recording => "recording",
+			MovieMode::Playing => "playing",
+			MovieMode::Finished => "finished",
+		}
+	}
+}
+
+/// Drives recording or playback of one movie, frame by frame.
+pub struct MovieSession {
+	mode: MovieMode,
+	movie: Option<Movie>,
+	frame: u32,
+	checkpoint_interval: u32,
+	desync_frame: Option<u32>,
+	// Power and reset commands for the frame being recorded
+	pending_commands: u8,
+}
+
+impl MovieSession {
+	pub fn new() -> Self {
+		Self {
+			mode: MovieMode::Idle,
+			movie: None,
+			frame: 0,
+			checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+			desync_frame: None,
+			pending_commands: 0,
+		}
+	}
+
+	pub fn start_recording(&mut self, movie: Movie, checkpoint_interval: u32) {
+		self.movie = Some(movie);
+		self.mode = MovieMode::Recording;
+		self.frame = 0;
+		self.checkpoint_interval = checkpoint_interval;
+		self.desync_frame = None;
+		self.pending_commands = 0;
+	}
+
+	pub fn start_playback(&mut self, movie: Movie) {
+		self.mode = match movie.frames.is_empty() {
+			true => MovieMode::Finished,
+			false => MovieMode::Playing,
+		};
+		self.movie = Some(movie);
+		self.frame = 0;
+		self.desync_frame = None;
+	}
+
+	pub fn stop(&mut self) {
+		self.mode = MovieMode::Idle;
+	}
+
+	pub fn is_playing(&self) -> bool {
+		self.mode == MovieMode::Playing
+	}
+
+	pub fn is_recording(&self) -> bool {
+		self.mode == MovieMode::Recording
+	}
+
+	/// Records `COMMAND_POWER` or `COMMAND_SOFT_RESET` on the upcoming frame,
+	/// for a power cycle or reset done since the last one.
+	pub fn record_command(&mut self, command: u8) {
+		if self.mode == MovieMode::Recording {
+			self.pending_commands |= command;
+		}
+	}
+
+	pub fn mode(&self) -> MovieMode {
+		self.mode
+	}
+
+	pub fn frame(&self) -> u32 {
+		self.frame
+	}
+
+	pub fn length(&self) -> u32 {
+		self.movie.as_ref().map_or(0, |movie| movie.frames.len() as u32)
+	}
+
+	/// First checkpoint whose hash didn't match during playback.
+	pub fn desync_frame(&self) -> Option<u32> {
+		self.desync_frame
+	}
+
+	pub fn movie(&self) -> Option<&Movie> {
+		self.movie.as_ref()
+	}
+
+	/// Input to apply before emulating the upcoming frame during playback.
+	pub fn next_frame(&self) -> Option<MovieFrame> {
+		match self.mode {
+			MovieMode::Playing => self.movie.as_ref()?.frames.get(self.frame as usize).copied(),
+			_ => None,
+		}
+	}
+
+	/// Called after each real (non speculative) frame.
+	pub fn end_frame(&mut self, nes: &Nes) {
+		let Some(movie) = self.movie.as_mut() else {
+			return;
+		};
+		match self.mode {
+			MovieMode::Recording => {
+				movie.frames.push(MovieFrame {
+					commands: std::mem::take(&mut self.pending_commands),
+					ports: [nes.joypad_state(0), nes.joypad_state(1)],
+				});
+				if self.checkpoint_interval > 0 && (self.frame + 1).is_multiple_of(self.checkpoint_interval) {
+					movie.checkpoints.push(Checkpoint {
+						frame: self.frame,
+						hash: frame_hash(nes),
+					});
+				}
+			}
+			MovieMode::Playing => {
+				if self.desync_frame.is_none() {
+					if let Some(expected) = movie.checkpoint(self.frame) {
+						if expected != frame_hash(nes) {
+							self.desync_frame = Some(self.frame);
+						}
+					}
+				}
+				if self.frame as usize + 1 >= movie.frames.len() {
+					self.mode = MovieMode::Finished;
+				}
+			}
+			MovieMode::Idle | MovieMode::Finished => return,
+		}
+		self.frame += 1;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fm2_round_trip() {
+		let mut movie = Movie::new(MovieAnchor::PowerOn);
+		movie.frames.push(MovieFrame { commands: 0, ports: [0x00, 0] });
+		movie.frames.push(MovieFrame { commands: 0, ports: [0x81, 0] });
+		movie.frames.push(MovieFrame { commands: 1, ports: [0x18, 0] });
+		movie.checkpoints.push(Checkpoint { frame: 1, hash: 0xdeadbeef });
+		let text = movie.to_fm2(Some("game.nes"));
+		assert!(text.contains("romFilename game.nes\n"));
+		assert!(text.contains("|0|R......A|||\n"));
+		assert!(text.contains("|1|...UT...|||\n"));
+
+		let parsed = Movie::from_fm2(&text).unwrap();
+		assert_eq!(movie.frames, parsed.frames);
+		assert_eq!(movie.checkpoints, parsed.checkpoints);
+		assert!(matches!(parsed.anchor, MovieAnchor::PowerOn));
+		assert_eq!(text, parsed.to_fm2(None));
+	}
+
+	#[test]
+	fn parses_fceux_movie() {
+		let text = "version 3\r\nemuVersion 20604\r\nromFilename smb\r\nguid 452DE2C3-EF43-2FA9-77AC-0677FC51543B\r\nport0 1\r\nport1 1\r\nport2 0\r\n|1|........|........||\r\n|0|.......A|R.......||\r\n";
+		let movie = Movie::from_fm2(text).unwrap();
+		assert_eq!(
+			vec![
+				MovieFrame { commands: 1, ports: [0, 0] },
+				MovieFrame { commands: 0, ports: [0x01, 0x80] },
+			],
+			movie.frames
+		);
+		assert!(movie.to_fm2(None).contains("guid 452DE2C3-EF43-2FA9-77AC-0677FC51543B\n"));
+	}
+
+	#[test]
+	fn pal_flag_round_trip() {
+		let mut movie = Movie::new(MovieAnchor::PowerOn);
+		assert!(!movie.is_pal());
+		movie.set_pal(true);
+		let text = movie.to_fm2(None);
+		assert_eq!(1, text.matches("palFlag").count());
+		assert!(Movie::from_fm2(&text).unwrap().is_pal());
+	}
+
+	#[test]
+	fn savestate_anchor_round_trip() {
+		let state: Vec<u8> = (0..=255).collect();
+		let movie = Movie::new(MovieAnchor::SaveState(state.clone()));
+		let parsed = Movie::from_fm2(&movie.to_fm2(None)).unwrap();
+		match parsed.anchor {
+			MovieAnchor::SaveState(parsed_state) => assert_eq!(state, parsed_state),
+			MovieAnchor::PowerOn => panic!("expected a savestate anchor"),
+		}
+	}
+
+	#[test]
+	fn rejects_invalid_movies() {
+		assert!(matches!(Movie::from_fm2("|0|........|||\n"), Err(MovieError::UnsupportedVersion(_))));
+		assert_eq!(
+			Err(MovieError::Malformed { line: 2, reason: "gamepad field must have 8 buttons" }),
+			Movie::from_fm2("version 3\n|0|...|||\n").map(|_| ())
+		);
+	}
+
+	#[test]
+	fn commands_round_trip() {
+		let nes = Nes::new(
+			Box::new(DefaultInput::new()),
+			Box::new(DefaultDisplay::new()),
+			Box::new(DefaultAudio::new()),
+		);
+		let mut session = MovieSession::new();
+		session.record_command(COMMAND_POWER);
+		session.start_recording(Movie::new(MovieAnchor::PowerOn), 0);
+		session.end_frame(&nes);
+		session.record_command(COMMAND_SOFT_RESET);
+		session.end_frame(&nes);
+		session.record_command(COMMAND_POWER);
+		session.record_command(COMMAND_SOFT_RESET);
+		session.end_frame(&nes);
+		session.end_frame(&nes);
+
+		let text = session.movie().unwrap().to_fm2(None);
+		let mut playback = MovieSession::new();
+		playback.start_playback(Movie::from_fm2(&text).unwrap());
+		let mut commands = Vec::new();
+		while let Some(frame) = playback.next_frame() {
+			commands.push(frame.commands);
+			playback.end_frame(&nes);
+		}
+		assert_eq!(vec![0, COMMAND_SOFT_RESET, COMMAND_POWER | COMMAND_SOFT_RESET, 0], commands);
+	}
+
+	// NROM image which counts the frames joypad 1 A is held in $01
+	fn test_rom() -> Vec<u8> {
+		let program = [
+			0xA9, 0x01, // LDA #$01
+			0x8D, 0x16, 0x40, // STA $4016
+			0xA9, 0x00, // LDA #$00
+			0x8D, 0x16, 0x40, // STA $4016
+			0xAD, 0x16, 0x40, // LDA $4016
+			0x29, 0x01, // AND #$01
+			0x18, // CLC
+			0x65, 0x01, // ADC $01
+			0x85, 0x01, // STA $01
+			0xE6, 0x00, // INC $00
+			0x4C, 0x00, 0x80, // JMP $8000
+		];
+		let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+		let mut prg = vec![0xEA; 0x4000];
+		prg[..program.len()].copy_from_slice(&program);
+		prg[0x3FFC] = 0x00;
+		prg[0x3FFD] = 0x80;
+		data.extend(prg);
+		data.extend(vec![0; 0x2000]);
+		data
+	}
+
+	// Runs `frames` frames the way `NativeNes::step_frame()` does, holding A
+	// every `period`th frame unless a movie is playing
+	fn run_frames(nes: &mut Nes, session: &mut MovieSession, rom: &[u8], frames: u32, period: u32) {
+		for frame in 0..frames {
+			match session.next_frame() {
+				Some(input) => {
+					if input.commands & COMMAND_POWER != 0 {
+						power_on(nes, rom);
+					}
+					nes.set_joypad_state(0, input.ports[0]);
+				}
+				None => nes.set_joypad_state(0, (frame % period == 0) as u8),
+			}
+			nes.step_frame();
+			session.end_frame(nes);
+		}
+	}
+
+	fn play(nes: &mut Nes, rom: &[u8], fm2: &str) -> MovieSession {
+		let movie = Movie::from_fm2(fm2).unwrap();
+		assert!(matches!(movie.anchor, MovieAnchor::PowerOn));
+		power_on(nes, rom);
+		let mut session = MovieSession::new();
+		let length = movie.frames.len() as u32;
+		session.start_playback(movie);
+		run_frames(nes, &mut session, rom, length, 1);
+		session
+	}
+
+	#[test]
+	fn record_and_play_back() {
+		let rom = test_rom();
+		let mut nes = Nes::new(
+			Box::new(DefaultInput::new()),
+			Box::new(DefaultDisplay::new()),
+			Box::new(DefaultAudio::new()),
+		);
+		nes.set_rom(Rom::new(rom.clone()));
+		nes.bootup();
+		let mut idle = MovieSession::new();
+		run_frames(&mut nes, &mut idle, &rom, 7, 3);
+
+		power_on(&mut nes, &rom);
+		let power_on_hash = frame_hash(&nes);
+		let mut session = MovieSession::new();
+		session.start_recording(Movie::new(MovieAnchor::PowerOn), 10);
+		run_frames(&mut nes, &mut session, &rom, 40, 4);
+		let fm2 = session.movie().unwrap().to_fm2(None);
+		assert_eq!(4, fm2.matches("comment checkpoint").count());
+
+		// Playback starts from another state than the recording did
+		run_frames(&mut nes, &mut idle, &rom, 13, 5);
+		power_on(&mut nes, &rom);
+		assert_eq!(power_on_hash, frame_hash(&nes));
+		let session = play(&mut nes, &rom, &fm2);
+		assert!(session.mode() == MovieMode::Finished);
+		assert_eq!((40, None), (session.frame(), session.desync_frame()));
+
+		// Holding A on frame 15 shows up at the next checkpoint, frame 19
+		let mut lines: Vec<&str> = fm2.lines().collect();
+		let first_frame = lines.iter().position(|line| line.starts_with('|')).unwrap();
+		assert_eq!("|0|........|||", lines[first_frame + 15]);
+		lines[first_frame + 15] = "|0|.......A|||";
+		let session = play(&mut nes, &rom, &(lines.join("\n") + "\n"));
+		assert_eq!(Some(19), session.desync_frame());
+	}
+}
@@ -0,0 +1,310 @@
+use nes_rust::display::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use nes_rust::palette::{chroma_angle, ntsc_signal, unpack_color, yuv_to_rgb, NtscPaletteParams};
+
+pub const DEFAULT_NTSC_WIDTH: u32 = 602;
+pub const MIN_NTSC_WIDTH: u32 = SCREEN_WIDTH;
+pub const MAX_NTSC_WIDTH: u32 = SCREEN_WIDTH * SAMPLES_PER_DOT as u32;
+
+// The PPU outputs 8 samples a dot at 12 samples per color subcarrier cycle
+const SAMPLES_PER_DOT: usize = 8;
+const PHASES: usize = 12;
+const ROW_SAMPLES: usize = SCREEN_WIDTH as usize * SAMPLES_PER_DOT;
+// A whole subcarrier cycle, which cancels chroma out of luma
+const NOTCH_SAMPLES: usize = PHASES;
+// Base width of the luma blur sharpness works against
+const SHARPNESS_SAMPLES: usize = 16;
+
+/// Knobs of the NTSC filter. -1.0 turns an effect off, 0.0 is what a
+/// composite TV shows, and up to 1.0 exaggerates it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NtscFilterParams {
+	pub picture: NtscPaletteParams,
+	/// Luma edges decoded as color, the rainbows on dithered patterns
+	pub artifacts: f32,
+	/// Luma smearing across color edges
+	pub fringing: f32,
+	/// Color smearing into the neighbouring dots
+	pub bleed: f32,
+	/// Luma sharpening, negative values blur
+	pub sharpness: f32,
+	/// Averages the two fields of the dot crawl cycle so artifacts stand still
+	pub merge_fields: bool,
+}
+
+impl NtscFilterParams {
+	pub fn composite() -> Self {
+		Self {
+			picture: NtscPaletteParams::default(),
+			artifacts: 0.0,
+			fringing: 0.0,
+			bleed: 0.0,
+			sharpness: 0.0,
+			merge_fields: false,
+		}
+	}
+
+	pub fn svideo() -> Self {
+		Self {
+			artifacts: -1.0,
+			fringing: -1.0,
+			sharpness: 0.2,
+			..Self::composite()
+		}
+	}
+
+	pub fn rgb() -> Self {
+		Self {
+			bleed: -1.0,
+			..Self::svideo()
+		}
+	}
+}
+
+/// Signal level NTSC filter in the spirit of blargg's nes_ntsc.
+///
+/// Each scanline is synthesized as the composite signal the PPU outputs for
+/// the palette indices, 8 samples a dot, and decoded back to YUV like a TV
+/// would: luma through a notch over a subcarrier cycle and chroma demodulated
+/// against the color burst. The subcarrier phase moves by a third of a cycle
+/// each scanline, and alternates between two fields as rendering skips a dot
+/// on odd frames, which is the dot crawl.
+pub struct NtscFilter {
+	params: NtscFilterParams,
+	width: usize,
+	// Signal per 9-bit palette index and subcarrier phase
+	signal: Vec<[f32; PHASES]>,
+	// YUV of each palette index without any artifact
+	colors: Vec<[f32; 3]>,
+	// U and V demodulation carriers per phase
+	carriers: [(f32, f32); PHASES],
+	field: usize,
+	samples: Vec<f32>,
+	ideal: Vec<[f32; 3]>,
+	notch: Vec<f32>,
+	luma: Vec<f32>,
+	blur: Vec<f32>,
+	u: Vec<f32>,
+	v: Vec<f32>,
+	filtered_u: Vec<f32>,
+	filtered_v: Vec<f32>,
+	sums: Vec<f32>,
+	// Decoded row, both fields when merging
+	row: Vec<[f32; 3]>,
+}
+
+impl NtscFilter {
+	/// # Arguments
+	/// * `width` Output pixels a scanline, MIN_NTSC_WIDTH - MAX_NTSC_WIDTH
+	pub fn new(params: NtscFilterParams, width: u32) -> Self {
+		let mut carriers = [(0.0, 0.0); PHASES];
+		for (phase, carrier) in carriers.iter_mut().enumerate() {
+			let angle = chroma_angle(phase, &params.picture);
+			*carrier = (angle.cos(), -angle.sin());
+		}
+		let signal: Vec<[f32; PHASES]> = (0..0x200u16)
+			.map(|index| {
+				let mut levels = [0.0; PHASES];
+				for (phase, level) in levels.iter_mut().enumerate() {
+					*level = ntsc_signal(index, phase);
+				}
+				levels
+			})
+			.collect();
+		let colors = signal
+			.iter()
+			.map(|levels| {
+				levels.iter().zip(carriers.iter()).fold([0.0; 3], |[y, u, v], (level, (cos, sin))| {
+					[y + level / PHASES as f32, u + 2.0 * level * cos / PHASES as f32, v + 2.0 * level * sin / PHASES as f32]
+				})
+			})
+			.collect();
+		Self {
+			params,
+			width: width.clamp(MIN_NTSC_WIDTH, MAX_NTSC_WIDTH) as usize,
+			signal,
+			colors,
+			carriers,
+			field: 0,
+			samples: vec![0.0; ROW_SAMPLES],
+			ideal: vec![[0.0; 3]; ROW_SAMPLES],
+			notch: vec![0.0; ROW_SAMPLES],
+			luma: vec![0.0; ROW_SAMPLES],
+			blur: vec![0.0; ROW_SAMPLES],
+			u: vec![0.0; ROW_SAMPLES],
+			v: vec![0.0; ROW_SAMPLES],
+			filtered_u: vec![0.0; ROW_SAMPLES],
+			filtered_v: vec![0.0; ROW_SAMPLES],
+			sums: Vec::with_capacity(ROW_SAMPLES + 1),
+			row: vec![[0.0; 3]; ROW_SAMPLES],
+		}
+	}
+
+	pub fn width(&self) -> u32 {
+		self.width as u32
+	}
+
+	/// Byte length of a filtered RGB frame
+	pub fn frame_len(&self) -> usize {
+		self.width * SCREEN_HEIGHT as usize * 3
+	}
+
+	/// Filters a frame of palette indices, see `Display::render_palette_index()`,
+	/// into `pixels`, `width()` x 240 RGB. Each call moves to the next field.
+	pub fn render(&mut self, indices: &[u16], pixels: &mut [u8]) {
+		let dot_width = SCREEN_WIDTH as usize;
+		for y in 0..SCREEN_HEIGHT as usize {
+			let indices = &indices[y * dot_width..(y + 1) * dot_width];
+			self.row.iter_mut().for_each(|value| *value = [0.0; 3]);
+			match self.params.merge_fields {
+				true => {
+					self.decode_row(indices, self.row_phase(y, 0), 0.5);
+					self.decode_row(indices, self.row_phase(y, 1), 0.5);
+				}
+				false => self.decode_row(indices, self.row_phase(y, self.field), 1.0),
+			}
+			self.resample_row(&mut pixels[y * self.width * 3..(y + 1) * self.width * 3]);
+		}
+		self.field ^= 1;
+	}
+
+	// First sample's subcarrier phase, a third of a cycle further each scanline
+	fn row_phase(&self, y: usize, field: usize) -> usize {
+		((y + field) % 3) * 4
+	}
+
+	fn decode_row(&mut self, indices: &[u16], phase: usize, weight: f32) {
+		for n in 0..ROW_SAMPLES {
+			let index = (indices[n / SAMPLES_PER_DOT] & 0x1FF) as usize;
+			self.samples[n] = self.signal[index][(n + phase) % PHASES];
+			self.ideal[n] = self.colors[index];
+		}
+		box_filter(&self.samples, &mut self.notch, &mut self.sums, NOTCH_SAMPLES);
+
+		let artifacts = effect_amount(self.params.artifacts);
+		let fringing = effect_amount(self.params.fringing);
+		for n in 0..ROW_SAMPLES {
+			let ideal_luma = self.ideal[n][0];
+			// The notch smears luma across a subcarrier cycle at color edges
+			self.luma[n] = ideal_luma + fringing * (self.notch[n] - ideal_luma);
+			// What the notch leaves out of luma is taken for chroma
+			let chroma = self.samples[n] - (ideal_luma + artifacts * (self.notch[n] - ideal_luma));
+			let (cos, sin) = self.carriers[(n + phase) % PHASES];
+			self.u[n] = 2.0 * chroma * cos;
+			self.v[n] = 2.0 * chroma * sin;
+		}
+		box_filter(&self.u, &mut self.filtered_u, &mut self.sums, PHASES);
+		box_filter(&self.v, &mut self.filtered_v, &mut self.sums, PHASES);
+		box_filter(&self.luma, &mut self.blur, &mut self.sums, SHARPNESS_SAMPLES);
+
+		let bleed = effect_amount(self.params.bleed);
+		let sharpness = self.params.sharpness;
+		for n in 0..ROW_SAMPLES {
+			let [_, ideal_u, ideal_v] = self.ideal[n];
+			let luma = self.luma[n] + sharpness * (self.luma[n] - self.blur[n]);
+			let u = ideal_u + bleed * (self.filtered_u[n] - ideal_u);
+			let v = ideal_v + bleed * (self.filtered_v[n] - ideal_v);
+			let row = &mut self.row[n];
+			row[0] += weight * luma;
+			row[1] += weight * u;
+			row[2] += weight * v;
+		}
+	}
+
+	fn resample_row(&self, pixels: &mut [u8]) {
+		let step = ROW_SAMPLES as f32 / self.width as f32;
+		for (x, rgb) in pixels.chunks_exact_mut(3).enumerate() {
+			let position = ((x as f32 + 0.5) * step - 0.5).clamp(0.0, (ROW_SAMPLES - 1) as f32);
+			let left = position as usize;
+			let right = (left + 1).min(ROW_SAMPLES - 1);
+			let fraction = position - left as f32;
+			let sample = |channel: usize| self.row[left][channel] + fraction * (self.row[right][channel] - self.row[left][channel]);
+			let (r, g, b) = unpack_color(yuv_to_rgb(sample(0), sample(1), sample(2), &self.params.picture));
+			rgb.copy_from_slice(&[r, g, b]);
+		}
+	}
+}
+
+// -1.0 - 1.0 settings to a 0.0 - 2.0 blend from the clean color
+fn effect_amount(setting: f32) -> f32 {
+	1.0 + setting.clamp(-1.0, 1.0)
+}
+
+// Mean over `width` samples around each sample, black past the ends
+fn box_filter(source: &[f32], target: &mut [f32], sums: &mut Vec<f32>, width: usize) {
+	sums.clear();
+	sums.push(0.0);
+	let mut total = 0.0;
+	for value in source {
+		total += value;
+		sums.push(total);
+	}
+	for (n, value) in target.iter_mut().enumerate() {
+		let start = n.saturating_sub(width / 2);
+		let end = (n + width - width / 2).min(source.len());
+		*value = (sums[end] - sums[start]) / width as f32;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use nes_rust::palette::generate_ntsc_palette;
+
+	const DOTS: usize = (SCREEN_WIDTH * SCREEN_HEIGHT) as usize;
+
+	fn pixel(pixels: &[u8], width: usize, x: usize, y: usize) -> (i32, i32, i32) {
+		let offset = (y * width + x) * 3;
+		(pixels[offset] as i32, pixels[offset + 1] as i32, pixels[offset + 2] as i32)
+	}
+
+	#[test]
+	fn flat_colors_match_the_generated_palette() {
+		let palette = generate_ntsc_palette(&NtscPaletteParams::default());
+		let mut filter = NtscFilter::new(NtscFilterParams::composite(), DEFAULT_NTSC_WIDTH);
+		let mut pixels = vec![0; filter.frame_len()];
+		for index in [0x16u16, 0x2A, 0x30, 0x20 | (1 << 6)] {
+			filter.render(&vec![index; DOTS], &mut pixels);
+			let (r, g, b) = unpack_color(palette[index as usize]);
+			let expected = (r as i32, g as i32, b as i32);
+			let (r, g, b) = pixel(&pixels, 602, 300, 100);
+			assert!((r - expected.0).abs() <= 2 && (g - expected.1).abs() <= 2 && (b - expected.2).abs() <= 2);
+		}
+	}
+
+	#[test]
+	fn dithering_artifacts() {
+		// Alternating black and white dots
+		let indices: Vec<u16> = (0..DOTS).map(|dot| if dot % 2 == 0 { 0x0F } else { 0x30 }).collect();
+		let chroma = |params: NtscFilterParams| {
+			let mut filter = NtscFilter::new(params, DEFAULT_NTSC_WIDTH);
+			let mut pixels = vec![0; filter.frame_len()];
+			let mut frames = Vec::new();
+			for _ in 0..2 {
+				filter.render(&indices, &mut pixels);
+				frames.push(pixels.clone());
+			}
+			let (r, g, b) = pixel(&frames[0], 602, 300, 100);
+			// Frames differ by more than rounding
+			let crawl = frames[0].iter().zip(frames[1].iter()).any(|(a, b)| a.abs_diff(*b) > 1);
+			((r - g).abs().max((g - b).abs()), crawl)
+		};
+		let (composite_chroma, composite_crawls) = chroma(NtscFilterParams::composite());
+		assert!(composite_chroma > 16);
+		assert!(composite_crawls);
+		let (svideo_chroma, svideo_crawls) = chroma(NtscFilterParams::svideo());
+		assert!(svideo_chroma <= 2);
+		assert!(!svideo_crawls);
+		let (_, merged_crawls) = chroma(NtscFilterParams {
+			merge_fields: true,
+			..NtscFilterParams::composite()
+		});
+		assert!(!merged_crawls);
+	}
+
+	#[test]
+	fn output_width() {
+		let filter = NtscFilter::new(NtscFilterParams::rgb(), 10000);
+		assert_eq!(MAX_NTSC_WIDTH, filter.width());
+		assert_eq!(2048 * 240 * 3, filter.frame_len());
+	}
+}
@@ -0,0 +1,212 @@
+use nes_rust::display::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use crate::ntsc::MAX_NTSC_WIDTH;
+
+// Past this many lines a side the picture is mostly gone
+pub const MAX_OVERSCAN: u32 = 64;
+// NTSC pixels are 8:7, the full 256 wide picture stretches to 292
+pub const ASPECT_WIDTH: u32 = SCREEN_WIDTH * 8 / 7;
+// The widest NTSC filter frame stretched to 8:7
+pub const MAX_FRAME_BYTE_LEN: usize = (MAX_NTSC_WIDTH * 8 / 7 * SCREEN_HEIGHT * 3) as usize;
+
+/// Pixels hidden on each side of the 256x240 picture.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Overscan {
+	pub top: u32,
+	pub bottom: u32,
+	pub left: u32,
+	pub right: u32,
+}
+
+// Source pixels an output pixel covers and how much of each, in 1/width units
+struct Tap {
+	start: usize,
+	weights: Vec<u32>,
+}
+
+/// Crops the overscan off RGB frames and optionally stretches them to the 8:7
+/// pixel aspect ratio. The stretch averages the source pixels by how much of
+/// each an output pixel covers, so flat areas and most edges stay sharp.
+/// Frames wider than 256 pixels, like the NTSC filter's, lose the overscan
+/// scaled to their width.
+pub struct FrameCrop {
+	overscan: Overscan,
+	aspect_correction: bool,
+	source_width: u32,
+	// Source pixels cut off the left of each row
+	left: u32,
+	width: u32,
+	height: u32,
+	// Empty without aspect correction
+	taps: Vec<Tap>,
+}
+
+impl FrameCrop {
+	/// Crops `source_width` x 240 frames. Panics if the overscan sides are past
+	/// `MAX_OVERSCAN` or `source_width` is past `MAX_NTSC_WIDTH`.
+	pub fn new(overscan: Overscan, aspect_correction: bool, source_width: u32) -> Self {
+		assert!(
+			[overscan.top, overscan.bottom, overscan.left, overscan.right]
+				.iter()
+				.all(|&side| side <= MAX_OVERSCAN),
+			"overscan sides must be at most {}",
+			MAX_OVERSCAN
+		);
+		assert!(source_width <= MAX_NTSC_WIDTH, "frames must be at most {} wide", MAX_NTSC_WIDTH);
+		let left = overscan.left * source_width / SCREEN_WIDTH;
+		let right = overscan.right * source_width / SCREEN_WIDTH;
+		let cropped_width = source_width - left - right;
+		let height = SCREEN_HEIGHT - overscan.top - overscan.bottom;
+		let (width, taps) = match aspect_correction {
+			true => {
+				let width = cropped_width * 8 / 7;
+				(width, build_taps(cropped_width as usize, width as usize))
+			}
+			false => (cropped_width, Vec::new()),
+		};
+		Self {
+			overscan,
+			aspect_correction,
+			source_width,
+			left,
+			width,
+			height,
+			taps,
+		}
+	}
+
+	/// The same crop for frames `source_width` wide
+	pub fn with_source_width(&self, source_width: u32) -> Self {
+		Self::new(self.overscan, self.aspect_correction, source_width)
+	}
+
+	pub fn width(&self) -> u32 {
+		self.width
+	}
+
+	pub fn height(&self) -> u32 {
+		self.height
+	}
+
+	pub fn frame_len(&self) -> usize {
+		(self.width * self.height * 3) as usize
+	}
+
+	/// `source` is a `source_width` x 240 RGB frame, `target` takes `frame_len()` bytes.
+	pub fn apply(&self, source: &[u8], target: &mut [u8]) {
+		let source_stride = self.source_width as usize * 3;
+		let target_stride = self.width as usize * 3;
+		let left = self.left as usize * 3;
+		for (y, row) in target.chunks_exact_mut(target_stride).take(self.height as usize).enumerate() {
+			let offset = (y + self.overscan.top as usize) * source_stride + left;
+			let source_row = &source[offset..];
+			if self.taps.is_empty() {
+				row.copy_from_slice(&source_row[..target_stride]);
+				continue;
+			}
+			for (pixel, tap) in row.chunks_exact_mut(3).zip(&self.taps) {
+				let mut sums = [0u32; 3];
+				for (i, weight) in tap.weights.iter().enumerate() {
+					let base = (tap.start + i) * 3;
+					for (sum, &value) in sums.iter_mut().zip(&source_row[base..base + 3]) {
+						*sum += value as u32 * weight;
+					}
+				}
+				let total: u32 = tap.weights.iter().sum();
+				for (channel, sum) in pixel.iter_mut().zip(sums) {
+					*channel = ((sum + total / 2) / total) as u8;
+				}
+			}
+		}
+	}
+}
+
+// Output pixel x spans [x * source, (x + 1) * source) and source pixel i
+// spans [i * target, (i + 1) * target), measured in the same units.
+fn build_taps(source: usize, target: usize) -> Vec<Tap> {
+	(0..target)
+		.map(|x| {
+			let span_start = x * source;
+			let span_end = (x + 1) * source;
+			let start = span_start / target;
+			let end = span_end.div_ceil(target).min(source);
+			let weights = (start..end)
+				.map(|i| (span_end.min((i + 1) * target) - span_start.max(i * target)) as u32)
+				.collect();
+			Tap { start, weights }
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn frame(pixel: impl Fn(usize, usize) -> [u8; 3]) -> Vec<u8> {
+		let mut frame = Vec::with_capacity((SCREEN_WIDTH * SCREEN_HEIGHT * 3) as usize);
+		for y in 0..SCREEN_HEIGHT as usize {
+			for x in 0..SCREEN_WIDTH as usize {
+				frame.extend_from_slice(&pixel(x, y));
+			}
+		}
+		frame
+	}
+
+	#[test]
+	fn crops_each_side() {
+		let crop = FrameCrop::new(
+			Overscan {
+				top: 8,
+				bottom: 8,
+				left: 4,
+				right: 12,
+			},
+			false,
+			SCREEN_WIDTH,
+		);
+		assert_eq!((240, 224), (crop.width(), crop.height()));
+		let source = frame(|x, y| [x as u8, y as u8, 0]);
+		let mut target = vec![0; crop.frame_len()];
+		crop.apply(&source, &mut target);
+		assert_eq!(&[4, 8, 0], &target[..3]);
+		let last = target.len() - 3;
+		assert_eq!(&[243, 231, 0], &target[last..]);
+	}
+
+	#[test]
+	fn aspect_correction() {
+		let crop = FrameCrop::new(Overscan::default(), true, SCREEN_WIDTH);
+		assert_eq!((ASPECT_WIDTH, SCREEN_HEIGHT), (crop.width(), crop.height()));
+		let mut target = vec![0; crop.frame_len()];
+		crop.apply(&frame(|_, _| [10, 200, 30]), &mut target);
+		assert!(target.chunks(3).all(|pixel| pixel == [10, 200, 30]));
+		// Edges between columns get at most one blended pixel
+		crop.apply(&frame(|x, _| [if x < 128 { 0 } else { 255 }; 3]), &mut target);
+		let row = &target[..ASPECT_WIDTH as usize * 3];
+		let blended = row.chunks(3).filter(|pixel| pixel[0] != 0 && pixel[0] != 255).count();
+		assert!(blended <= 1);
+		assert_eq!(0, row[0]);
+		assert_eq!(255, row[row.len() - 1]);
+	}
+
+	#[test]
+	fn wide_frames_crop_in_proportion() {
+		let overscan = Overscan {
+			top: 0,
+			bottom: 0,
+			left: 8,
+			right: 16,
+		};
+		// 4 pixels for each of the picture's
+		let crop = FrameCrop::new(overscan, false, SCREEN_WIDTH * 4);
+		assert_eq!(((256 - 24) * 4, SCREEN_HEIGHT), (crop.width(), crop.height()));
+		let source: Vec<u8> = (0..SCREEN_HEIGHT as usize * 1024).flat_map(|i| [(i % 1024 / 4) as u8, 0, 0]).collect();
+		let mut target = vec![0; crop.frame_len()];
+		crop.apply(&source, &mut target);
+		assert_eq!(8, target[0]);
+		assert_eq!(239, target[crop.width() as usize * 3 - 3]);
+		let widest = FrameCrop::new(Overscan::default(), true, MAX_NTSC_WIDTH);
+		assert_eq!(MAX_FRAME_BYTE_LEN, widest.frame_len());
+		assert_eq!(SCREEN_WIDTH - 24, crop.with_source_width(SCREEN_WIDTH).width());
+	}
+}
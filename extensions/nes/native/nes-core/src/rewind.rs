@@ -0,0 +1,268 @@
+use std::collections::VecDeque;
+
+pub const DEFAULT_REWIND_BUDGET_BYTES: usize = 8 * 1024 * 1024;
+pub const DEFAULT_REWIND_INTERVAL: u32 = 1;
+
+// Literal runs end once this many unchanged bytes follow, so short gaps
+// inside a changed region don't cost a new run header each.
+const MIN_ZERO_RUN: usize = 4;
+
+/// Rolling history of save states.
+///
+/// Only the newest snapshot is kept in full. Every older snapshot is stored as
+/// the XOR of itself against its successor, run-length encoded, so walking back
+/// one step is a single in-place XOR over the newest snapshot.
+pub struct RewindBuffer {
+	budget_bytes: usize,
+	interval: u32,
+	frames_since_snapshot: u32,
+	current: Option<Vec<u8>>,
+	deltas: VecDeque<Vec<u8>>,
+	delta_bytes: usize,
+}
+
+impl RewindBuffer {
+	pub fn new(budget_bytes: usize, interval: u32) -> Self {
+		Self {
+			budget_bytes,
+			interval: interval.max(1),
+			frames_since_snapshot: 0,
+			current: None,
+			deltas: VecDeque::new(),
+			delta_bytes: 0,
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.budget_bytes > 0
+	}
+
+	pub fn configure(&mut self, budget_bytes: usize, interval: u32) {
+		self.budget_bytes = budget_bytes;
+		self.interval = interval.max(1);
+		self.clear();
+	}
+
+	pub fn clear(&mut self) {
+		self.frames_since_snapshot = 0;
+		self.current = None;
+		self.deltas.clear();
+		self.delta_bytes = 0;
+	}
+
+	/// Called once per emulated frame. `capture` is only invoked on frames
+	/// where a snapshot is due.
+	pub fn record_frame<F: FnOnce() -> Vec<u8>>(&mut self, capture: F) {
+		if !self.is_enabled() {
+			return;
+		}
+		self.frames_since_snapshot += 1;
+		if self.current.is_some() && self.frames_since_snapshot < self.interval {
+			return;
+		}
+		self.frames_since_snapshot = 0;
+		self.push(capture());
+	}
+
+	fn push(&mut self, state: Vec<u8>) {
+		if let Some(previous) = self.current.take() {
+			if previous.len() == state.len() {
+				let delta = encode_delta(&previous, &state);
+				self.delta_bytes += delta.len();
+				self.deltas.push_back(delta);
+			} else {
+				self.deltas.clear();
+				self.delta_bytes = 0;
+			}
+		}
+		self.current = Some(state);
+		self.enforce_budget();
+	}
+
+	fn enforce_budget(&mut self) {
+		let current_len = self.current.as_ref().map_or(0, |state| state.len());
+		while current_len + self.delta_bytes > self.budget_bytes {
+			match self.deltas.pop_front() {
+				Some(delta) => self.delta_bytes -= delta.len(),
+				None => break,
+			}
+		}
+	}
+
+	/// Number of frames that can currently be rewound.
+	pub fn available_frames(&self) -> u32 {
+		match self.current {
+			Some(_) => self.deltas.len() as u32 * self.interval + self.frames_since_snapshot,
+			None => 0,
+		}
+	}
+
+	/// Walks back at least `frames` frames, or as far as the history allows.
+	/// Returns the state to restore and the number of frames actually rewound,
+	/// counting the frames run since the newest snapshot.
+	pub fn rewind(&mut self, frames: u32) -> Option<(&[u8], u32)> {
+		if frames == 0 {
+			return None;
+		}
+		let current = self.current.as_mut()?;
+		let steps = frames
+			.saturating_sub(self.frames_since_snapshot)
+			.div_ceil(self.interval)
+			.min(self.deltas.len() as u32);
+		let rewound = steps * self.interval + self.frames_since_snapshot;
+		if rewound == 0 {
+			return None;
+		}
+		for _ in 0..steps {
+			if let Some(delta) = self.deltas.pop_back() {
+				self.delta_bytes -= delta.len();
+				apply_delta(&delta, current);
+			}
+		}
+		self.frames_since_snapshot = 0;
+		Some((current.as_slice(), rewound))
+	}
+}
+
+/// Encodes `previous ^ next` as a sequence of
+/// (unchanged run length, changed run length, changed XOR bytes) records
+/// with LEB128 lengths.
+fn encode_delta(previous: &[u8], next: &[u8]) -> Vec<u8> {
+	let len = previous.len();
+	let mut delta = Vec::new();
+	let mut pos = 0;
+	while pos < len {
+		let zero_start = pos;
+		while pos < len && previous[pos] == next[pos] {
+			pos += 1;
+		}
+		if pos == len {
+			break;
+		}
+		let literal_start = pos;
+		let mut zeros = 0;
+		while pos < len && zeros < MIN_ZERO_RUN {
+			zeros = if previous[pos] == next[pos] { zeros + 1 } else { 0 };
+			pos += 1;
+		}
+		pos -= zeros;
+		write_varint(&mut delta, literal_start - zero_start);
+		write_varint(&mut delta, pos - literal_start);
+		delta.extend(
+			previous[literal_start..pos]
+				.iter()
+				.zip(&next[literal_start..pos])
+				.map(|(a, b)| a ^ b),
+		);
+	}
+	delta
+}
+
+fn apply_delta(delta: &[u8], target: &mut [u8]) {
+	let mut cursor = 0;
+	let mut pos = 0;
+	while cursor < delta.len() {
+		pos += read_varint(delta, &mut cursor);
+		let literal_len = read_varint(delta, &mut cursor);
+		for value in &mut target[pos..pos + literal_len] {
+			*value ^= delta[cursor];
+			cursor += 1;
+		}
+		pos += literal_len;
+	}
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+	loop {
+		let byte = (value & 0x7f) as u8;
+		value >>= 7;
+		if value == 0 {
+			out.push(byte);
+			return;
+		}
+		out.push(byte | 0x80);
+	}
+}
+
+fn read_varint(data: &[u8], cursor: &mut usize) -> usize {
+	let mut value = 0;
+	let mut shift = 0;
+	loop {
+		let byte = data[*cursor];
+		*cursor += 1;
+		value |= ((byte & 0x7f) as usize) << shift;
+		if byte & 0x80 == 0 {
+			return value;
+		}
+		shift += 7;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn delta_round_trip() {
+		let previous: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+		let mut next = previous.clone();
+		next[0] ^= 1;
+		next[10] = 0xff;
+		next[12] = 0xee;
+		next[500..700].iter_mut().for_each(|value| *value = 0);
+		next[999] = 3;
+		let delta = encode_delta(&previous, &next);
+		let mut restored = next.clone();
+		apply_delta(&delta, &mut restored);
+		assert_eq!(previous, restored);
+	}
+
+	#[test]
+	fn unchanged_state_has_empty_delta() {
+		let state = vec![5; 64];
+		assert!(encode_delta(&state, &state).is_empty());
+	}
+
+	#[test]
+	fn rewinds_through_history() {
+		let mut buffer = RewindBuffer::new(1024 * 1024, 2);
+		for frame in 0..10u8 {
+			buffer.record_frame(|| vec![frame; 32]);
+		}
+		// Snapshots at frames 0, 2, 4, 6, 8, and frame 9 has run since
+		assert_eq!(9, buffer.available_frames());
+		let (state, rewound) = buffer.rewind(3).unwrap();
+		assert_eq!(3, rewound);
+		assert_eq!(vec![6; 32], state.to_vec());
+		let (state, rewound) = buffer.rewind(100).unwrap();
+		assert_eq!(6, rewound);
+		assert_eq!(vec![0; 32], state.to_vec());
+		assert!(buffer.rewind(1).is_none());
+	}
+
+	#[test]
+	fn restores_newest_snapshot_without_history() {
+		let mut buffer = RewindBuffer::new(1024 * 1024, 4);
+		for frame in 0..4u8 {
+			buffer.record_frame(|| vec![frame; 32]);
+		}
+		assert_eq!(3, buffer.available_frames());
+		let (state, rewound) = buffer.rewind(2).unwrap();
+		assert_eq!(3, rewound);
+		assert_eq!(vec![0; 32], state.to_vec());
+		assert_eq!(0, buffer.available_frames());
+		assert!(buffer.rewind(1).is_none());
+	}
+
+	#[test]
+	fn drops_oldest_history_over_budget() {
+		let mut buffer = RewindBuffer::new(200, 1);
+		for frame in 0..100u8 {
+			buffer.record_frame(|| vec![frame; 32]);
+		}
+		assert_eq!(4, buffer.available_frames());
+		let frames = buffer.available_frames();
+		let (state, _) = buffer.rewind(frames).unwrap();
+		assert_eq!(vec![99 - frames as u8; 32], state.to_vec());
+	}
+}
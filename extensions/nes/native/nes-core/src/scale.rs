@@ -0,0 +1,760 @@
+pub const MAX_SCALE_FACTOR: u32 = 6;
+
+// hqx treats colors as different past these YUV distances
+const HQ_THRESHOLD_Y: i32 = 48;
+const HQ_THRESHOLD_U: i32 = 7;
+const HQ_THRESHOLD_V: i32 = 6;
+
+// xBRZ's defaults
+const XBRZ_LUMINANCE_WEIGHT: f32 = 1.0;
+const XBRZ_EQUAL_COLOR_TOLERANCE: f32 = 30.0;
+const XBRZ_DOMINANT_DIRECTION_THRESHOLD: f32 = 3.6;
+const XBRZ_STEEP_DIRECTION_THRESHOLD: f32 = 2.2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scaler {
+	/// Integer nearest neighbour, 2-6
+	Nearest(u32),
+	/// EPX / AdvMAME2x
+	Scale2x,
+	/// AdvMAME3x
+	Scale3x,
+	Hq2x,
+	Hq3x,
+	/// 2-6
+	Xbrz(u32),
+}
+
+impl Scaler {
+	pub fn factor(&self) -> u32 {
+		match self {
+			Scaler::Nearest(factor) | Scaler::Xbrz(factor) => (*factor).clamp(2, MAX_SCALE_FACTOR),
+			Scaler::Scale2x | Scaler::Hq2x => 2,
+			Scaler::Scale3x | Scaler::Hq3x => 3,
+		}
+	}
+}
+
+/// Pixel art upscaler over RGB frames, keeping its working buffers between frames.
+pub struct Upscaler {
+	scaler: Scaler,
+	pixels: Vec<u32>,
+	// xBRZ's blend of each pixel's corners
+	corners: Vec<u8>,
+	scaled: Vec<u32>,
+}
+
+impl Upscaler {
+	pub fn new(scaler: Scaler) -> Self {
+		Self {
+			scaler,
+			pixels: Vec::new(),
+			corners: Vec::new(),
+			scaled: Vec::new(),
+		}
+	}
+
+	pub fn factor(&self) -> u32 {
+		self.scaler.factor()
+	}
+
+	/// Scales `source`, `width` x `height` RGB, into `target`, `factor()` times
+	/// as wide and high.
+	pub fn scale(&mut self, source: &[u8], width: usize, height: usize, target: &mut [u8]) {
+		let factor = self.factor() as usize;
+		self.pixels.clear();
+		self.pixels.extend(
+			source[..width * height * 3]
+				.chunks_exact(3)
+				.map(|rgb| ((rgb[0] as u32) << 16) | ((rgb[1] as u32) << 8) | rgb[2] as u32),
+		);
+		self.scaled.resize(width * height * factor * factor, 0);
+		let image = Image {
+			pixels: &self.pixels,
+			width,
+			height,
+		};
+		match self.scaler {
+			Scaler::Nearest(_) => nearest(&image, factor, &mut self.scaled),
+			Scaler::Scale2x => scale2x(&image, &mut self.scaled),
+			Scaler::Scale3x => scale3x(&image, &mut self.scaled),
+			Scaler::Hq2x | Scaler::Hq3x => hqx(&image, factor, &mut self.scaled),
+			Scaler::Xbrz(_) => xbrz(&image, factor, &mut self.corners, &mut self.scaled),
+		}
+		for (rgb, c) in target.chunks_exact_mut(3).zip(self.scaled.iter()) {
+			rgb[0] = (c >> 16) as u8;
+			rgb[1] = (c >> 8) as u8;
+			rgb[2] = *c as u8;
+		}
+	}
+}
+
+struct Image<'a> {
+	pixels: &'a [u32],
+	width: usize,
+	height: usize,
+}
+
+impl Image<'_> {
+	// Neighbour of (x, y), repeating the border past the edges
+	fn at(&self, x: usize, y: usize, dx: isize, dy: isize) -> u32 {
+		let x = (x as isize + dx).clamp(0, self.width as isize - 1) as usize;
+		let y = (y as isize + dy).clamp(0, self.height as isize - 1) as usize;
+		self.pixels[y * self.width + x]
+	}
+
+	// Writes the `factor` x `factor` block of (x, y), row by row
+	fn put_block(&self, x: usize, y: usize, factor: usize, block: &[u32], scaled: &mut [u32]) {
+		let out_width = self.width * factor;
+		for (j, row) in block.chunks_exact(factor).enumerate() {
+			let offset = (y * factor + j) * out_width + x * factor;
+			scaled[offset..offset + factor].copy_from_slice(row);
+		}
+	}
+}
+
+fn nearest(image: &Image, factor: usize, scaled: &mut [u32]) {
+	let block_len = factor * factor;
+	for y in 0..image.height {
+		for x in 0..image.width {
+			let block = [image.at(x, y, 0, 0); (MAX_SCALE_FACTOR * MAX_SCALE_FACTOR) as usize];
+			image.put_block(x, y, factor, &block[..block_len], scaled);
+		}
+	}
+}
+
+// Refer to https://www.scale2x.it/algorithm
+//   A
+// C P B
+//   D
+fn scale2x(image: &Image, scaled: &mut [u32]) {
+	for y in 0..image.height {
+		for x in 0..image.width {
+			let w = |dx, dy| image.at(x, y, dx, dy);
+			let (p, a, b, c, d) = (w(0, 0), w(0, -1), w(1, 0), w(-1, 0), w(0, 1));
+			let block = [
+				if c == a && c != d && a != b { a } else { p },
+				if a == b && a != c && b != d { b } else { p },
+				if d == c && d != b && c != a { c } else { p },
+				if b == d && b != a && d != c { d } else { p },
+			];
+			image.put_block(x, y, 2, &block, scaled);
+		}
+	}
+}
+
+// A B C
+// D E F
+// G H I
+fn scale3x(image: &Image, scaled: &mut [u32]) {
+	for y in 0..image.height {
+		for x in 0..image.width {
+			let w = |dx, dy| image.at(x, y, dx, dy);
+			let (a, b, c) = (w(-1, -1), w(0, -1), w(1, -1));
+			let (d, e, f) = (w(-1, 0), w(0, 0), w(1, 0));
+			let (g, h, i) = (w(-1, 1), w(0, 1), w(1, 1));
+			let block = match b != h && d != f {
+				true => [
+					if d == b { d } else { e },
+					if (d == b && e != c) || (b == f && e != a) { b } else { e },
+					if b == f { f } else { e },
+					if (d == b && e != g) || (d == h && e != a) { d } else { e },
+					e,
+					if (b == f && e != i) || (h == f && e != c) { f } else { e },
+					if d == h { d } else { e },
+					if (d == h && e != i) || (h == f && e != g) { h } else { e },
+					if h == f { f } else { e },
+				],
+				false => [e; 9],
+			};
+			image.put_block(x, y, 3, &block, scaled);
+		}
+	}
+}
+
+/// hq2x and hq3x. Each neighbour that differs from the center in YUV past the
+/// hqx thresholds sets a bit of an 8 bit pattern, and the pattern picks how
+/// every output pixel interpolates from the original case tables. The tables
+/// are stored for the top left output pixel and the top edge on hq3x, the
+/// other pixels use them on the neighbourhood turned around.
+///
+/// w1 w2 w3
+/// w4 w5 w6
+/// w7 w8 w9
+fn hqx(image: &Image, factor: usize, scaled: &mut [u32]) {
+	let mut block = [0; 9];
+	for y in 0..image.height {
+		for x in 0..image.width {
+			let w: [u32; 9] = std::array::from_fn(|k| image.at(x, y, k as isize % 3 - 1, k as isize / 3 - 1));
+			let yuv = w.map(hq_yuv);
+			block[..factor * factor].fill(w[4]);
+			for rotation in 0..4 {
+				// Where w[k] of the turned neighbourhood is in w
+				let at = |k: usize| {
+					let (dx, dy) = rotate(k as isize % 3 - 1, k as isize / 3 - 1, rotation);
+					((dy + 1) * 3 + dx + 1) as usize
+				};
+				let differs = |a: usize, b: usize| hq_differs(yuv[at(a)], yuv[at(b)]);
+				let pattern = [0, 1, 2, 3, 5, 6, 7, 8]
+					.iter()
+					.enumerate()
+					.filter(|(_, k)| differs(4, **k))
+					.fold(0, |pattern, (bit, _)| pattern | 1 << bit);
+				let apply = |rule: HqRule| {
+					let weights = match rule {
+						HqRule::Blend(weights) => weights,
+						HqRule::LeftTop(a, b) => if differs(3, 1) { a } else { b },
+						HqRule::TopRight(a, b) => if differs(1, 5) { a } else { b },
+						HqRule::BottomLeft(a, b) => if differs(7, 3) { a } else { b },
+					};
+					blend(&[(w[4], weights[0]), (w[at(0)], weights[1]), (w[at(1)], weights[2]), (w[at(3)], weights[3])])
+				};
+				let mut put = |i: usize, j: usize, color| {
+					let (i, j) = rotate_block(i, j, factor, rotation);
+					block[j * factor + i] = color;
+				};
+				match factor {
+					2 => put(0, 0, apply(HQ2X_RULES[HQ2X[pattern] as usize])),
+					_ => {
+						put(0, 0, apply(HQ3X_CORNER_RULES[HQ3X_CORNER[pattern] as usize]));
+						put(1, 0, apply(HQ3X_EDGE_RULES[HQ3X_EDGE[pattern] as usize]));
+					}
+				}
+			}
+			image.put_block(x, y, factor, &block[..factor * factor], scaled);
+		}
+	}
+}
+
+// Weights out of 16 of the center, top left, top and left pixels
+type HqWeights = [u32; 4];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum HqRule {
+	Blend(HqWeights),
+	// The first weights when the two pixels differ, the second otherwise
+	LeftTop(HqWeights, HqWeights),
+	TopRight(HqWeights, HqWeights),
+	BottomLeft(HqWeights, HqWeights),
+}
+
+// The case tables index the rules by pattern, with bit 0 set when w1 differs
+// from the center up to bit 7 for w9
+const HQ2X_RULES: [HqRule; 14] = [
+	HqRule::Blend([8, 0, 4, 4]),
+	HqRule::Blend([8, 4, 0, 4]),
+	HqRule::Blend([12, 0, 0, 4]),
+	HqRule::Blend([8, 4, 4, 0]),
+	HqRule::Blend([12, 0, 4, 0]),
+	HqRule::LeftTop([12, 4, 0, 0], [8, 0, 4, 4]),
+	HqRule::LeftTop([16, 0, 0, 0], [8, 0, 4, 4]),
+	HqRule::LeftTop([12, 4, 0, 0], [4, 0, 6, 6]),
+	HqRule::LeftTop([16, 0, 0, 0], [4, 0, 6, 6]),
+	HqRule::TopRight([12, 0, 0, 4], [10, 0, 4, 2]),
+	HqRule::Blend([12, 4, 0, 0]),
+	HqRule::LeftTop([12, 4, 0, 0], [12, 0, 2, 2]),
+	HqRule::LeftTop([16, 0, 0, 0], [14, 0, 1, 1]),
+	HqRule::BottomLeft([12, 0, 4, 0], [10, 0, 2, 4]),
+];
+const HQ2X: [u8; 256] = [
+	0, 0, 1, 2, 0, 0, 1, 2, 3, 4, 5, 6, 3, 4, 7, 8,
+	0, 0, 1, 9, 0, 0, 1, 9, 3, 4, 6, 6, 3, 4, 10, 6,
+	0, 0, 1, 2, 0, 0, 1, 2, 3, 4, 7, 8, 3, 4, 11, 12,
+	0, 0, 1, 9, 0, 0, 1, 9, 3, 4, 11, 6, 3, 4, 10, 12,
+	0, 0, 1, 2, 0, 0, 1, 2, 3, 13, 6, 6, 3, 13, 11, 6,
+	0, 0, 1, 2, 0, 0, 1, 2, 3, 4, 11, 6, 3, 4, 11, 6,
+	0, 0, 1, 2, 0, 0, 1, 2, 3, 13, 10, 6, 3, 13, 10, 12,
+	0, 0, 1, 2, 0, 0, 1, 9, 3, 4, 11, 6, 3, 13, 10, 12,
+	0, 0, 1, 2, 0, 0, 1, 2, 3, 4, 5, 6, 3, 4, 7, 8,
+	0, 0, 1, 2, 0, 0, 1, 2, 3, 4, 11, 6, 3, 4, 11, 6,
+	0, 0, 1, 2, 0, 0, 1, 2, 3, 4, 7, 8, 3, 4, 11, 12,
+	0, 0, 1, 2, 0, 0, 1, 2, 3, 4, 11, 8, 3, 4, 10, 12,
+	0, 0, 1, 2, 0, 0, 1, 2, 3, 4, 11, 6, 3, 4, 11, 8,
+	0, 0, 1, 2, 0, 0, 1, 2, 3, 4, 11, 6, 3, 4, 10, 6,
+	0, 0, 1, 2, 0, 0, 1, 2, 3, 4, 11, 6, 3, 4, 10, 12,
+	0, 0, 1, 2, 0, 0, 1, 2, 3, 4, 10, 6, 3, 4, 10, 12,
+];
+
+const HQ3X_CORNER_RULES: [HqRule; 12] = [
+	HqRule::Blend([8, 0, 4, 4]),
+	HqRule::Blend([12, 4, 0, 0]),
+	HqRule::Blend([12, 0, 0, 4]),
+	HqRule::Blend([12, 0, 4, 0]),
+	HqRule::LeftTop([12, 4, 0, 0], [2, 0, 7, 7]),
+	HqRule::LeftTop([16, 0, 0, 0], [2, 0, 7, 7]),
+	HqRule::LeftTop([12, 4, 0, 0], [0, 0, 8, 8]),
+	HqRule::LeftTop([16, 0, 0, 0], [0, 0, 8, 8]),
+	HqRule::TopRight([12, 0, 0, 4], [8, 0, 4, 4]),
+	HqRule::LeftTop([12, 4, 0, 0], [8, 0, 4, 4]),
+	HqRule::LeftTop([16, 0, 0, 0], [8, 0, 4, 4]),
+	HqRule::BottomLeft([12, 0, 4, 0], [8, 0, 4, 4]),
+];
+const HQ3X_CORNER: [u8; 256] = [
+	0, 0, 1, 2, 0, 0, 1, 2, 1, 3, 4, 5, 1, 3, 6, 7,
+	0, 0, 1, 8, 0, 0, 1, 8, 1, 3, 5, 5, 1, 3, 1, 5,
+	0, 0, 1, 2, 0, 0, 1, 2, 1, 3, 6, 7, 1, 3, 9, 10,
+	0, 0, 1, 8, 0, 0, 1, 8, 1, 3, 9, 5, 1, 3, 1, 10,
+	0, 0, 1, 2, 0, 0, 1, 2, 1, 11, 5, 5, 1, 11, 9, 5,
+	0, 0, 1, 2, 0, 0, 1, 2, 1, 3, 9, 5, 1, 3, 9, 5,
+	0, 0, 1, 2, 0, 0, 1, 2, 1, 11, 1, 5, 1, 11, 1, 10,
+	0, 0, 1, 2, 0, 0, 1, 8, 1, 3, 9, 5, 1, 11, 1, 10,
+	0, 0, 1, 2, 0, 0, 1, 2, 1, 3, 4, 5, 1, 3, 6, 7,
+	0, 0, 1, 2, 0, 0, 1, 2, 1, 3, 9, 5, 1, 3, 9, 5,
+	0, 0, 1, 2, 0, 0, 1, 2, 1, 3, 6, 7, 1, 3, 9, 10,
+	0, 0, 1, 2, 0, 0, 1, 2, 1, 3, 9, 7, 1, 3, 1, 10,
+	0, 0, 1, 2, 0, 0, 1, 2, 1, 3, 9, 5, 1, 3, 9, 7,
+	0, 0, 1, 2, 0, 0, 1, 2, 1, 3, 9, 5, 1, 3, 1, 5,
+	0, 0, 1, 2, 0, 0, 1, 2, 1, 3, 9, 5, 1, 3, 1, 10,
+	0, 0, 1, 2, 0, 0, 1, 2, 1, 3, 1, 5, 1, 3, 1, 10,
+];
+
+const HQ3X_EDGE_RULES: [HqRule; 8] = [
+	HqRule::Blend([12, 0, 4, 0]),
+	HqRule::Blend([16, 0, 0, 0]),
+	HqRule::LeftTop([16, 0, 0, 0], [14, 0, 2, 0]),
+	HqRule::LeftTop([16, 0, 0, 0], [4, 0, 12, 0]),
+	HqRule::TopRight([16, 0, 0, 0], [14, 0, 2, 0]),
+	HqRule::TopRight([16, 0, 0, 0], [4, 0, 12, 0]),
+	HqRule::LeftTop([16, 0, 0, 0], [12, 0, 4, 0]),
+	HqRule::TopRight([16, 0, 0, 0], [12, 0, 4, 0]),
+];
+const HQ3X_EDGE: [u8; 256] = [
+	0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 3, 3,
+	0, 0, 4, 5, 0, 0, 4, 5, 0, 0, 1, 2, 0, 0, 4, 1,
+	0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 6, 6, 0, 0, 1, 1,
+	0, 0, 4, 5, 0, 0, 4, 5, 0, 0, 1, 2, 0, 0, 4, 1,
+	0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 2,
+	0, 0, 4, 1, 0, 0, 4, 4, 0, 0, 1, 2, 0, 0, 4, 1,
+	0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 1, 1,
+	0, 0, 1, 1, 0, 0, 4, 5, 0, 0, 1, 2, 0, 0, 4, 2,
+	0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 3, 3,
+	0, 0, 7, 1, 0, 0, 7, 1, 0, 0, 1, 2, 0, 0, 4, 1,
+	0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 6, 6, 0, 0, 1, 1,
+	0, 0, 7, 1, 0, 0, 7, 1, 0, 0, 1, 6, 0, 0, 7, 1,
+	0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 1, 3,
+	0, 0, 1, 1, 0, 0, 4, 1, 0, 0, 1, 2, 0, 0, 4, 4,
+	0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 1, 1,
+	0, 0, 1, 1, 0, 0, 4, 1, 0, 0, 1, 2, 0, 0, 4, 1,
+];
+
+
+fn hq_differs(a: (i32, i32, i32), b: (i32, i32, i32)) -> bool {
+	(a.0 - b.0).abs() > HQ_THRESHOLD_Y || (a.1 - b.1).abs() > HQ_THRESHOLD_U || (a.2 - b.2).abs() > HQ_THRESHOLD_V
+}
+
+fn hq_yuv(c: u32) -> (i32, i32, i32) {
+	let (r, g, b) = channels(c);
+	let (r, g, b) = (r as f32, g as f32, b as f32);
+	let y = 0.299 * r + 0.587 * g + 0.114 * b;
+	let u = -0.169 * r - 0.331 * g + 0.5 * b + 128.0;
+	let v = 0.5 * r - 0.419 * g - 0.081 * b + 128.0;
+	(y as i32, u as i32, v as i32)
+}
+
+/// xBRZ. A first pass finds the edges through every corner shared by four
+/// pixels with a weighted 4x4 kernel, then each pixel blends the corners on
+/// the far side of an edge with the output patterns of its scale factor: a
+/// rounded corner, or a shallow, steep or 45 degree line.
+fn xbrz(image: &Image, factor: usize, corners: &mut Vec<u8>, scaled: &mut [u32]) {
+	corners.clear();
+	corners.resize(image.width * image.height, 0);
+	// Kernels on the last row or column are flat, the border repeats
+	for y in 0..image.height.saturating_sub(1) {
+		for x in 0..image.width.saturating_sub(1) {
+			let [f, g, j, k] = xbrz_corner(image, x, y);
+			let index = y * image.width + x;
+			corners[index] |= f << (2 * XBRZ_BOTTOM_RIGHT);
+			corners[index + 1] |= g << (2 * XBRZ_BOTTOM_LEFT);
+			corners[index + image.width] |= j << (2 * XBRZ_TOP_RIGHT);
+			corners[index + image.width + 1] |= k << (2 * XBRZ_TOP_LEFT);
+		}
+	}
+	let patterns = &XBRZ_PATTERNS[factor - 2];
+	let mut block = [0; (MAX_SCALE_FACTOR * MAX_SCALE_FACTOR) as usize];
+	for y in 0..image.height {
+		for x in 0..image.width {
+			let kernel: [u32; 9] = std::array::from_fn(|k| image.at(x, y, k as isize % 3 - 1, k as isize / 3 - 1));
+			block[..factor * factor].fill(kernel[4]);
+			let info = corners[y * image.width + x];
+			if info != 0 {
+				// Bottom right, top right, top left and bottom left corners
+				for rotation in [0, 3, 2, 1] {
+					xbrz_blend(&kernel, info, rotation, patterns, factor, &mut block);
+				}
+			}
+			image.put_block(x, y, factor, &block[..factor * factor], scaled);
+		}
+	}
+}
+
+// How strongly to blend a corner, 2 bits per corner in the order below
+const XBRZ_BLEND_NONE: u8 = 0;
+const XBRZ_BLEND_NORMAL: u8 = 1;
+const XBRZ_BLEND_DOMINANT: u8 = 2;
+const XBRZ_TOP_LEFT: usize = 0;
+const XBRZ_TOP_RIGHT: usize = 1;
+const XBRZ_BOTTOM_RIGHT: usize = 2;
+const XBRZ_BOTTOM_LEFT: usize = 3;
+
+// a b c d
+// e f g h
+// i j k l
+// m n o p
+// Blend of the f, g, j and k corners around the middle of the kernel at (x, y)
+fn xbrz_corner(image: &Image, x: usize, y: usize) -> [u8; 4] {
+	let w = |dx, dy| image.at(x, y, dx, dy);
+	let (b, c) = (w(0, -1), w(1, -1));
+	let (e, f, g, h) = (w(-1, 0), w(0, 0), w(1, 0), w(2, 0));
+	let (i, j, k, l) = (w(-1, 1), w(0, 1), w(1, 1), w(2, 1));
+	let (n, o) = (w(0, 2), w(1, 2));
+	let mut blend = [XBRZ_BLEND_NONE; 4];
+	// Flat, or parallel lines through the middle
+	if (f == g && j == k) || (f == j && g == k) {
+		return blend;
+	}
+	let jg = xbrz_distance(i, f) + xbrz_distance(f, c) + xbrz_distance(n, k) + xbrz_distance(k, h) + 4.0 * xbrz_distance(j, g);
+	let fk = xbrz_distance(e, j) + xbrz_distance(j, o) + xbrz_distance(b, g) + xbrz_distance(g, l) + 4.0 * xbrz_distance(f, k);
+	let strength = |low: f32, high: f32| match XBRZ_DOMINANT_DIRECTION_THRESHOLD * low < high {
+		true => XBRZ_BLEND_DOMINANT,
+		false => XBRZ_BLEND_NORMAL,
+	};
+	if jg < fk {
+		// An edge along j-g, cutting the f and k corners
+		if f != g && f != j {
+			blend[0] = strength(jg, fk);
+		}
+		if k != j && k != g {
+			blend[3] = strength(jg, fk);
+		}
+	} else if fk < jg {
+		if j != f && j != k {
+			blend[2] = strength(fk, jg);
+		}
+		if g != f && g != k {
+			blend[1] = strength(fk, jg);
+		}
+	}
+	blend
+}
+
+// a b c
+// d e f
+// g h i
+// Blends the bottom right corner of e in `kernel`, row by row, after
+// `rotation` quarter turns clockwise of both the kernel and the output block.
+fn xbrz_blend(kernel: &[u32; 9], info: u8, rotation: usize, patterns: &XbrzPatterns, factor: usize, block: &mut [u32]) {
+	// Corner index after the turn
+	let corner = |index: usize| (info >> (2 * ((index + rotation) % 4))) & 3;
+	if corner(XBRZ_BOTTOM_RIGHT) == XBRZ_BLEND_NONE {
+		return;
+	}
+	let w = |dx, dy| {
+		let (dx, dy) = rotate(dx, dy, rotation);
+		kernel[((dy + 1) * 3 + dx + 1) as usize]
+	};
+	let (b, c) = (w(0, -1), w(1, -1));
+	let (d, e, f) = (w(-1, 0), w(0, 0), w(1, 0));
+	let (g, h, i) = (w(-1, 1), w(0, 1), w(1, 1));
+	let line = match corner(XBRZ_BOTTOM_RIGHT) >= XBRZ_BLEND_DOMINANT {
+		true => true,
+		// Single pixels and L shapes only get their corners rounded, unless a
+		// neighbouring corner continues the line at 90 degrees
+		false => {
+			(corner(XBRZ_TOP_RIGHT) == XBRZ_BLEND_NONE || xbrz_equal(e, g))
+				&& (corner(XBRZ_BOTTOM_LEFT) == XBRZ_BLEND_NONE || xbrz_equal(e, c))
+				&& (xbrz_equal(e, i) || !(xbrz_equal(g, h) && xbrz_equal(h, i) && xbrz_equal(i, f) && xbrz_equal(f, c)))
+		}
+	};
+	let color = match xbrz_distance(e, f) <= xbrz_distance(e, h) {
+		true => f,
+		false => h,
+	};
+	let (pattern, transpose) = match line {
+		true => {
+			let fg = xbrz_distance(f, g);
+			let hc = xbrz_distance(h, c);
+			let shallow = XBRZ_STEEP_DIRECTION_THRESHOLD * fg <= hc && e != g && d != g;
+			let steep = XBRZ_STEEP_DIRECTION_THRESHOLD * hc <= fg && e != c && b != c;
+			match (shallow, steep) {
+				(true, true) => (patterns.steep_and_shallow, false),
+				(true, false) => (patterns.shallow, false),
+				// A steep line is the shallow one mirrored along the diagonal
+				(false, true) => (patterns.shallow, true),
+				(false, false) => (patterns.diagonal, false),
+			}
+		}
+		false => (patterns.corner, false),
+	};
+	for &(row, column, alpha, total) in pattern {
+		let (row, column) = match transpose {
+			true => (column, row),
+			false => (row, column),
+		};
+		let (i, j) = rotate_block(column, row, factor, rotation);
+		let pixel = &mut block[j * factor + i];
+		*pixel = blend(&[(color, alpha), (*pixel, total - alpha)]);
+	}
+}
+
+// Output pixels the blend color goes into for the bottom right corner, as
+// row, column and the color's share out of a total
+type XbrzPattern = &'static [(usize, usize, u32, u32)];
+
+struct XbrzPatterns {
+	shallow: XbrzPattern,
+	steep_and_shallow: XbrzPattern,
+	diagonal: XbrzPattern,
+	corner: XbrzPattern,
+}
+
+const XBRZ_PATTERNS: [XbrzPatterns; 5] = [
+	XbrzPatterns {
+		shallow: &[(1, 0, 1, 4), (1, 1, 3, 4)],
+		steep_and_shallow: &[(1, 0, 1, 4), (0, 1, 1, 4), (1, 1, 5, 6)],
+		diagonal: &[(1, 1, 1, 2)],
+		corner: &[(1, 1, 21, 100)],
+	},
+	XbrzPatterns {
+		shallow: &[(2, 0, 1, 4), (1, 2, 1, 4), (2, 1, 3, 4), (2, 2, 1, 1)],
+		steep_and_shallow: &[(2, 0, 1, 4), (0, 2, 1, 4), (2, 1, 3, 4), (1, 2, 3, 4), (2, 2, 1, 1)],
+		diagonal: &[(1, 2, 1, 8), (2, 1, 1, 8), (2, 2, 7, 8)],
+		corner: &[(2, 2, 45, 100)],
+	},
+	XbrzPatterns {
+		shallow: &[(3, 0, 1, 4), (2, 2, 1, 4), (3, 1, 3, 4), (2, 3, 3, 4), (3, 2, 1, 1), (3, 3, 1, 1)],
+		steep_and_shallow: &[
+			(3, 1, 3, 4),
+			(1, 3, 3, 4),
+			(3, 0, 1, 4),
+			(0, 3, 1, 4),
+			(2, 2, 1, 3),
+			(3, 3, 1, 1),
+			(3, 2, 1, 1),
+			(2, 3, 1, 1),
+		],
+		diagonal: &[(3, 2, 1, 2), (2, 3, 1, 2), (3, 3, 1, 1)],
+		corner: &[(3, 3, 68, 100), (3, 2, 9, 100), (2, 3, 9, 100)],
+	},
+	XbrzPatterns {
+		shallow: &[
+			(4, 0, 1, 4),
+			(3, 2, 1, 4),
+			(2, 4, 1, 4),
+			(4, 1, 3, 4),
+			(3, 3, 3, 4),
+			(4, 2, 1, 1),
+			(4, 3, 1, 1),
+			(4, 4, 1, 1),
+			(3, 4, 1, 1),
+		],
+		steep_and_shallow: &[
+			(0, 4, 1, 4),
+			(2, 3, 1, 4),
+			(1, 4, 3, 4),
+			(4, 0, 1, 4),
+			(3, 2, 1, 4),
+			(4, 1, 3, 4),
+			(3, 3, 2, 3),
+			(2, 4, 1, 1),
+			(3, 4, 1, 1),
+			(4, 4, 1, 1),
+			(4, 2, 1, 1),
+			(4, 3, 1, 1),
+		],
+		diagonal: &[(4, 2, 1, 8), (3, 3, 1, 8), (2, 4, 1, 8), (4, 3, 7, 8), (3, 4, 7, 8), (4, 4, 1, 1)],
+		corner: &[(4, 4, 86, 100), (4, 3, 23, 100), (3, 4, 23, 100)],
+	},
+	XbrzPatterns {
+		shallow: &[
+			(5, 0, 1, 4),
+			(4, 2, 1, 4),
+			(3, 4, 1, 4),
+			(5, 1, 3, 4),
+			(4, 3, 3, 4),
+			(3, 5, 3, 4),
+			(5, 2, 1, 1),
+			(5, 3, 1, 1),
+			(5, 4, 1, 1),
+			(5, 5, 1, 1),
+			(4, 4, 1, 1),
+			(4, 5, 1, 1),
+		],
+		steep_and_shallow: &[
+			(0, 5, 1, 4),
+			(2, 4, 1, 4),
+			(1, 5, 3, 4),
+			(3, 4, 3, 4),
+			(5, 0, 1, 4),
+			(4, 2, 1, 4),
+			(5, 1, 3, 4),
+			(4, 3, 3, 4),
+			(2, 5, 1, 1),
+			(3, 5, 1, 1),
+			(4, 5, 1, 1),
+			(5, 5, 1, 1),
+			(4, 4, 1, 1),
+			(5, 4, 1, 1),
+			(5, 2, 1, 1),
+			(5, 3, 1, 1),
+		],
+		diagonal: &[(5, 3, 1, 2), (4, 4, 1, 2), (3, 5, 1, 2), (4, 5, 1, 1), (5, 5, 1, 1), (5, 4, 1, 1)],
+		corner: &[(5, 5, 97, 100), (4, 5, 42, 100), (5, 4, 42, 100), (5, 3, 6, 100), (3, 5, 6, 100)],
+	},
+];
+
+// Quarter turns clockwise
+fn rotate(dx: isize, dy: isize, rotation: usize) -> (isize, isize) {
+	(0..rotation).fold((dx, dy), |(dx, dy), _| (-dy, dx))
+}
+
+// Output pixel (i, j) of a `factor` x `factor` block after the same turns
+fn rotate_block(i: usize, j: usize, factor: usize, rotation: usize) -> (usize, usize) {
+	let center = factor as isize - 1;
+	let (i, j) = rotate(2 * i as isize - center, 2 * j as isize - center, rotation);
+	(((i + center) / 2) as usize, ((j + center) / 2) as usize)
+}
+
+fn xbrz_equal(a: u32, b: u32) -> bool {
+	xbrz_distance(a, b) < XBRZ_EQUAL_COLOR_TOLERANCE
+}
+
+// Distance in YCbCr with the BT.2020 weights
+fn xbrz_distance(a: u32, b: u32) -> f32 {
+	if a == b {
+		return 0.0;
+	}
+	let (ra, ga, ba) = channels(a);
+	let (rb, gb, bb) = channels(b);
+	let r = ra as f32 - rb as f32;
+	let g = ga as f32 - gb as f32;
+	let b = ba as f32 - bb as f32;
+	const K_B: f32 = 0.0593;
+	const K_R: f32 = 0.2627;
+	const K_G: f32 = 1.0 - K_B - K_R;
+	let y = K_R * r + K_G * g + K_B * b;
+	let c_b = 0.5 / (1.0 - K_B) * (b - y);
+	let c_r = 0.5 / (1.0 - K_R) * (r - y);
+	((XBRZ_LUMINANCE_WEIGHT * y).powi(2) + c_b.powi(2) + c_r.powi(2)).sqrt()
+}
+
+fn channels(c: u32) -> (u32, u32, u32) {
+	((c >> 16) & 0xFF, (c >> 8) & 0xFF, c & 0xFF)
+}
+
+// Weighted average of colors
+fn blend(colors: &[(u32, u32)]) -> u32 {
+	let total: u32 = colors.iter().map(|(_, weight)| weight).sum();
+	let (r, g, b) = colors.iter().fold((0, 0, 0), |(r, g, b), (c, weight)| {
+		let (cr, cg, cb) = channels(*c);
+		(r + cr * weight, g + cg * weight, b + cb * weight)
+	});
+	((r / total) << 16) | ((g / total) << 8) | (b / total)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const BLACK: u32 = 0x000000;
+	const WHITE: u32 = 0xFFFFFF;
+
+	fn scale(scaler: Scaler, pixels: &[u32], width: usize) -> Vec<u32> {
+		let height = pixels.len() / width;
+		let source: Vec<u8> = pixels.iter().flat_map(|c| [(c >> 16) as u8, (c >> 8) as u8, *c as u8]).collect();
+		let mut upscaler = Upscaler::new(scaler);
+		let factor = upscaler.factor() as usize;
+		let mut target = vec![0; source.len() * factor * factor];
+		upscaler.scale(&source, width, height, &mut target);
+		target.chunks_exact(3).map(|rgb| ((rgb[0] as u32) << 16) | ((rgb[1] as u32) << 8) | rgb[2] as u32).collect()
+	}
+
+	// Black below the diagonal of a 4x4 image
+	fn staircase() -> Vec<u32> {
+		(0..16).map(|i| if i % 4 <= i / 4 { BLACK } else { WHITE }).collect()
+	}
+
+	#[test]
+	fn flat_images_stay_flat() {
+		let scalers = [
+			Scaler::Nearest(4),
+			Scaler::Scale2x,
+			Scaler::Scale3x,
+			Scaler::Hq2x,
+			Scaler::Hq3x,
+			Scaler::Xbrz(5),
+		];
+		for scaler in scalers {
+			let factor = scaler.factor() as usize;
+			assert_eq!(vec![0x123456; 12 * factor * factor], scale(scaler, &[0x123456; 12], 4));
+		}
+	}
+
+	#[test]
+	fn nearest_and_scale2x() {
+		assert_eq!(vec![BLACK, BLACK, WHITE, WHITE, BLACK, BLACK, WHITE, WHITE], scale(Scaler::Nearest(2), &[BLACK, WHITE], 2));
+		let scaled = scale(Scaler::Scale2x, &staircase(), 4);
+		// The white pixel right of the diagonal at (1, 0) gets its bottom left corner filled
+		assert_eq!(WHITE, scaled[2]);
+		assert_eq!(BLACK, scaled[8 + 2]);
+		assert_eq!(WHITE, scaled[8 + 3]);
+	}
+
+	#[test]
+	fn smoothing_scalers_blend_diagonals() {
+		for scaler in [Scaler::Hq2x, Scaler::Hq3x, Scaler::Xbrz(3)] {
+			let factor = scaler.factor() as usize;
+			let scaled = scale(scaler, &staircase(), 4);
+			// Bottom left corner of the white pixel at (2, 1)
+			let corner = scaled[(factor * 2 - 1) * 4 * factor + 2 * factor];
+			assert!(corner != WHITE && corner != BLACK);
+		}
+		assert_eq!(MAX_SCALE_FACTOR, Scaler::Xbrz(10).factor());
+	}
+
+	#[test]
+	fn hqx_rounds_single_pixels() {
+		let mut dot = [WHITE; 9];
+		dot[4] = BLACK;
+		// hq2x's corners lean 1/16 to each side, hq3x's corners blend them half
+		// and half with the center and leave the edges alone
+		let scaled = scale(Scaler::Hq2x, &dot, 3);
+		assert_eq!([0x1F1F1F, 0x1F1F1F], [scaled[2 * 6 + 2], scaled[3 * 6 + 3]]);
+		let scaled = scale(Scaler::Hq3x, &dot, 3);
+		assert_eq!([0x7F7F7F, BLACK, BLACK], [scaled[3 * 9 + 3], scaled[3 * 9 + 4], scaled[4 * 9 + 4]]);
+		// xBRZ rounds each corner off by 21%
+		let scaled = scale(Scaler::Xbrz(2), &dot, 3);
+		assert_eq!([0x353535, 0x353535], [scaled[2 * 6 + 2], scaled[3 * 6 + 3]]);
+	}
+
+	// The tables have to stay the same when the neighbourhood is mirrored
+	#[test]
+	fn hqx_tables_are_symmetric() {
+		fn mirror(pattern: usize, pairs: &[(usize, usize)]) -> usize {
+			pairs.iter().fold(pattern, |mirrored, &(a, b)| {
+				let swapped = mirrored & !(1 << a | 1 << b);
+				swapped | (pattern >> a & 1) << b | (pattern >> b & 1) << a
+			})
+		}
+		// Through the top left corner, swapping top and left
+		let diagonal = |rule| match rule {
+			HqRule::Blend([c, w1, w2, w4]) => HqRule::Blend([c, w1, w4, w2]),
+			HqRule::LeftTop([c, w1, w2, w4], [d, v1, v2, v4]) => HqRule::LeftTop([c, w1, w4, w2], [d, v1, v4, v2]),
+			HqRule::TopRight([c, w1, w2, w4], [d, v1, v2, v4]) => HqRule::BottomLeft([c, w1, w4, w2], [d, v1, v4, v2]),
+			HqRule::BottomLeft([c, w1, w2, w4], [d, v1, v2, v4]) => HqRule::TopRight([c, w1, w4, w2], [d, v1, v4, v2]),
+		};
+		// Left to right, for the top edge
+		let vertical = |rule| match rule {
+			HqRule::LeftTop(a, b) => HqRule::TopRight(a, b),
+			HqRule::TopRight(a, b) => HqRule::LeftTop(a, b),
+			rule => rule,
+		};
+		for pattern in 0..256 {
+			let mirrored = mirror(pattern, &[(1, 3), (2, 5), (4, 6)]);
+			let rule = |rules: &[HqRule], table: &[u8; 256], pattern: usize| rules[table[pattern] as usize];
+			assert_eq!(diagonal(rule(&HQ2X_RULES, &HQ2X, pattern)), rule(&HQ2X_RULES, &HQ2X, mirrored));
+			assert_eq!(diagonal(rule(&HQ3X_CORNER_RULES, &HQ3X_CORNER, pattern)), rule(&HQ3X_CORNER_RULES, &HQ3X_CORNER, mirrored));
+			let mirrored = mirror(pattern, &[(0, 2), (3, 4), (5, 7)]);
+			assert_eq!(vertical(rule(&HQ3X_EDGE_RULES, &HQ3X_EDGE, pattern)), rule(&HQ3X_EDGE_RULES, &HQ3X_EDGE, mirrored));
+		}
+	}
+}
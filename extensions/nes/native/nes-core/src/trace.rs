@@ -0,0 +1,59 @@
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+use std::rc::Rc;
+
+pub const DEFAULT_TRACE_BUFFER_BYTES: usize = 16 * 1024 * 1024;
+
+/// Trace sink shared with `NativeNes` so JS can drain it in chunks.
+///
+/// Writes fail once `capacity` is reached, which stops the tracer rather
+/// than growing without bound when nobody drains it.
+#[derive(Clone)]
+pub struct TraceBuffer {
+	data: Rc<RefCell<Vec<u8>>>,
+	capacity: usize,
+}
+
+impl TraceBuffer {
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			data: Rc::new(RefCell::new(Vec::new())),
+			capacity,
+		}
+	}
+
+	pub fn take(&self) -> Vec<u8> {
+		std::mem::take(&mut *self.data.borrow_mut())
+	}
+}
+
+impl Write for TraceBuffer {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let mut data = self.data.borrow_mut();
+		if data.len() + buf.len() > self.capacity {
+			return Err(io::Error::other("trace buffer is full"));
+		}
+		data.extend_from_slice(buf);
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn capacity_and_take() {
+		let buffer = TraceBuffer::new(8);
+		let mut sink = buffer.clone();
+		assert!(writeln!(sink, "abc").is_ok());
+		assert!(writeln!(sink, "defgh").is_err());
+		assert_eq!(b"abc\n".to_vec(), buffer.take());
+		assert!(writeln!(sink, "defgh").is_ok());
+	}
+}
@@ -5,7 +5,7 @@ import { DEFAULT_INPUT_MAPPING, getMappedButtons } from "./input-map.js";
 import type { NesButton, FrameBuffer, NesCore } from "./nes-core.js";
 import type { NesSessionStats } from "./nes-session.js";
 import type { RendererMode } from "./config.js";
-import { FRAME_HEIGHT, FRAME_WIDTH, NesImageRenderer } from "./renderer.js";
+import { NesImageRenderer } from "./renderer.js";
 
 function readRgb(frameBuffer: FrameBuffer, index: number): [number, number, number] {
 	const data = frameBuffer.data;
@@ -49,15 +49,15 @@ function averageBlock(
 	blockWidth: number,
 	blockHeight: number,
 ): [number, number, number] {
-	const endX = Math.min(startX + blockWidth, FRAME_WIDTH);
-	const endY = Math.min(startY + blockHeight, FRAME_HEIGHT);
+	const endX = Math.min(startX + blockWidth, frameBuffer.width);
+	const endY = Math.min(startY + blockHeight, frameBuffer.height);
 	let rSum = 0;
 	let gSum = 0;
 	let bSum = 0;
 	let count = 0;
 
 	for (let y = startY; y < endY; y += 1) {
-		const rowOffset = y * FRAME_WIDTH;
+		const rowOffset = y * frameBuffer.width;
 		for (let x = startX; x < endX; x += 1) {
 			const [r, g, b] = readRgb(frameBuffer, rowOffset + x);
 			rSum += r;
@@ -189,10 +189,11 @@ export class NesOverlayComponent implements Component {
 		footerRows: number,
 	): { lines: string[]; padPrefix: string } {
 		const maxFrameRows = Math.max(1, this.tui.terminal.rows - footerRows);
-		const scaleX = Math.max(1, Math.ceil(FRAME_WIDTH / width));
-		const scaleY = Math.max(1, Math.ceil(FRAME_HEIGHT / (maxFrameRows * 2)));
-		const targetRows = Math.max(1, Math.floor(FRAME_HEIGHT / (scaleY * 2)));
-		const targetCols = Math.max(1, Math.floor(FRAME_WIDTH / scaleX));
+		const { width: frameWidth, height: frameHeight } = frameBuffer;
+		const scaleX = Math.max(1, Math.ceil(frameWidth / width));
+		const scaleY = Math.max(1, Math.ceil(frameHeight / (maxFrameRows * 2)));
+		const targetRows = Math.max(1, Math.floor(frameHeight / (scaleY * 2)));
+		const targetCols = Math.max(1, Math.floor(frameWidth / scaleX));
 		const padLeft = Math.max(0, Math.floor((width - targetCols) / 2));
 		const padPrefix = padLeft > 0 ? " ".repeat(padLeft) : "";
 
@@ -258,7 +259,7 @@ export class NesOverlayComponent implements Component {
 			const cpuLine = `CPU pc=${this.formatHex(cpu.pc, 4)} op=${this.formatHex(cpu.lastOpcode, 2)} `
 				+ `a=${this.formatHex(cpu.a, 2)} x=${this.formatHex(cpu.x, 2)} y=${this.formatHex(cpu.y, 2)} `
 				+ `sp=${this.formatHex(cpu.sp, 2)} p=${this.formatHex(cpu.p, 2)} `
-				+ `last=${this.formatHex(cpu.lastPc, 4)}`;
+				+ `last=${this.formatHex(cpu.lastPc, 4)}${cpu.jammed ? " JAMMED" : ""}`;
 			const mapperLine = `MMC1 ctrl=${this.formatHex(mapper.control, 2)} prg=${this.formatHex(mapper.prg, 2)} `
 				+ `chr0=${this.formatHex(mapper.chr0, 2)} chr1=${this.formatHex(mapper.chr1, 2)} `
 				+ `prgMode=${mapper.prgMode} chrMode=${mapper.chrMode} outer=${mapper.outerPrg}`;
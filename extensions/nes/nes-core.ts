@@ -4,9 +4,25 @@ const require = createRequire(import.meta.url);
 
 export type NesButton = "up" | "down" | "left" | "right" | "a" | "b" | "start" | "select";
 export type VideoFilterMode = "off" | "ntsc-composite" | "ntsc-svideo" | "ntsc-rgb";
+export type NesRegion = "auto" | "ntsc" | "pal" | "dendy";
+export type NesPalettePreset = "default" | "fceux" | "nestopia" | "smooth" | "composite-direct";
+// A preset or the contents of a .pal file
+export type NesPalette = NesPalettePreset | Uint8Array;
+export type NesScaler = "off" | "nearest" | "scale2x" | "scale3x" | "hq2x" | "hq3x" | "xbrz";
 
+// Pixels cropped from each side of the picture
+export interface NesOverscan {
+	top: number;
+	bottom: number;
+	left: number;
+	right: number;
+}
+
+// RGB pixels, width and height change with the upscaler
 export interface FrameBuffer {
 	data: Uint8Array;
+	width: number;
+	height: number;
 }
 
 export interface NesCpuDebugState {
@@ -18,6 +34,7 @@ export interface NesCpuDebugState {
 	p: number;
 	lastPc: number;
 	lastOpcode: number;
+	jammed: boolean;
 }
 
 export interface NesMapperDebugState {
@@ -36,23 +53,51 @@ export interface NesDebugState {
 	mapper: NesMapperDebugState;
 }
 
+export type NesCheatFormat = "game-genie" | "pro-action-replay" | "raw";
+
+export interface NesCheat {
+	id: number;
+	code: string;
+	format: NesCheatFormat;
+	address: number;
+	value: number;
+	compare?: number;
+	enabled: boolean;
+}
+
 export interface NesCore {
 	loadRom(rom: Uint8Array): void;
 	tick(): void;
+	setRunAhead(frames: number, secondInstance?: boolean): void;
 	getFrameBuffer(): FrameBuffer;
 	setButton(button: NesButton, pressed: boolean): void;
 	getSram(): Uint8Array | null;
 	setSram(sram: Uint8Array): void;
 	isSramDirty(): boolean;
 	markSramSaved(): void;
+	saveState(): Uint8Array;
+	loadState(state: Uint8Array): void;
+	setRewind(budgetBytes?: number, frameInterval?: number): void;
+	rewind(frames: number): number;
+	getFrameRate(): number;
 	getAudioWarning(): string | null;
 	getDebugState(): NesDebugState | null;
+	addCheat(code: string): number;
+	removeCheat(id: number): boolean;
+	setCheatEnabled(id: number, enabled: boolean): boolean;
+	listCheats(): NesCheat[];
+	setPalette(palette: NesPalette): void;
+	setScaler(scaler: NesScaler, factor?: number): void;
+	setOverscan(overscan: NesOverscan, aspectCorrection: boolean): void;
+	// Publishes frames to a named shared memory segment, null removes it
+	setSharedFramebuffer(name: string | null): void;
 	dispose(): void;
 }
 
 export interface CreateNesCoreOptions {
 	enableAudio?: boolean;
 	videoFilter?: VideoFilterMode;
+	region?: NesRegion;
 }
 
 interface NativeNesInstance {
@@ -60,7 +105,11 @@ interface NativeNesInstance {
 	bootup(): void;
 	stepFrame(): void;
 	refreshFramebuffer(): void;
+	setRunAhead(frames: number, secondInstance?: boolean): void;
 	setVideoFilter(mode: number): void;
+	setNtscFilter(options?: { preset?: number; width?: number } | null): void;
+	setRegion(mode: number): void;
+	getFrameRate(): number;
 	setAudioEnabled(enabled: boolean): boolean;
 	pressButton(button: number): void;
 	releaseButton(button: number): void;
@@ -69,7 +118,23 @@ interface NativeNesInstance {
 	setSram(data: Uint8Array): void;
 	isSramDirty(): boolean;
 	markSramSaved(): void;
+	saveState(): Uint8Array;
+	loadState(data: Uint8Array): void;
+	setRewind(budgetBytes?: number, frameInterval?: number): void;
+	getRewindFrames(): number;
+	rewind(frames: number): number;
 	getDebugState(): NesDebugState;
+	addCheat(code: string): number;
+	removeCheat(id: number): boolean;
+	setCheatEnabled(id: number, enabled: boolean): boolean;
+	listCheats(): NesCheat[];
+	setPalette(data: Uint8Array): void;
+	generatePalette(options?: { preset?: number }): void;
+	resetPalette(): void;
+	setScaler(mode: number, factor?: number): void;
+	setOverscan(options?: Partial<NesOverscan> & { aspectCorrection?: boolean }): void;
+	setSharedFramebuffer(name?: string | null): void;
+	getFramebufferSize(): { width: number; height: number };
 	getFramebuffer(): Uint8Array;
 }
 
@@ -105,31 +170,60 @@ const NATIVE_BUTTON_MAP: Record<NesButton, number> = {
 	right: 7,
 };
 
-const NATIVE_VIDEO_FILTER_MAP: Record<VideoFilterMode, number> = {
+const NATIVE_NTSC_PRESET_MAP: Record<Exclude<VideoFilterMode, "off">, number> = {
+	"ntsc-composite": 0,
+	"ntsc-svideo": 1,
+	"ntsc-rgb": 2,
+};
+
+// NES pixels wide, so the renderer's layout and the overscan stay as without the filter
+const NTSC_FILTER_WIDTH = 256;
+
+const NATIVE_REGION_MAP: Record<NesRegion, number> = {
+	auto: 0,
+	ntsc: 1,
+	pal: 2,
+	dendy: 3,
+};
+
+const NATIVE_PALETTE_PRESET_MAP: Record<Exclude<NesPalettePreset, "default">, number> = {
+	"composite-direct": 0,
+	fceux: 1,
+	nestopia: 2,
+	smooth: 3,
+};
+
+const NATIVE_SCALER_MAP: Record<NesScaler, number> = {
 	off: 0,
-	"ntsc-composite": 1,
-	"ntsc-svideo": 2,
-	"ntsc-rgb": 3,
+	nearest: 1,
+	scale2x: 2,
+	scale3x: 3,
+	hq2x: 4,
+	hq3x: 5,
+	xbrz: 6,
 };
 
 class NativeNesCore implements NesCore {
 	private readonly nes: NativeNesInstance;
 	private readonly audioWarning: string | null;
-	private readonly frameBuffer: Uint8Array;
+	private frameBuffer: FrameBuffer;
 	private hasSram = false;
 
-	constructor(enableAudio: boolean, videoFilter: VideoFilterMode) {
+	constructor(enableAudio: boolean, videoFilter: VideoFilterMode, region: NesRegion) {
 		const module = getNativeModule();
 		if (!module) {
 			throw new Error("Native NES core addon is not available.");
 		}
 		this.nes = new module.NativeNes();
-		this.nes.setVideoFilter(NATIVE_VIDEO_FILTER_MAP[videoFilter]);
+		this.nes.setNtscFilter(
+			videoFilter === "off" ? null : { preset: NATIVE_NTSC_PRESET_MAP[videoFilter], width: NTSC_FILTER_WIDTH },
+		);
+		this.nes.setRegion(NATIVE_REGION_MAP[region]);
 		const audioEnabled = this.nes.setAudioEnabled(enableAudio);
 		this.audioWarning = enableAudio && !audioEnabled
 			? "Audio output unavailable. Rebuild the native core with --features audio-cpal."
 			: null;
-		this.frameBuffer = this.nes.getFramebuffer();
+		this.frameBuffer = this.readFrameBuffer();
 	}
 
 	loadRom(rom: Uint8Array): void {
@@ -143,8 +237,12 @@ class NativeNesCore implements NesCore {
 		this.nes.refreshFramebuffer();
 	}
 
+	setRunAhead(frames: number, secondInstance = false): void {
+		this.nes.setRunAhead(frames, secondInstance);
+	}
+
 	getFrameBuffer(): FrameBuffer {
-		return { data: this.frameBuffer };
+		return this.frameBuffer;
 	}
 
 	setButton(button: NesButton, pressed: boolean): void {
@@ -184,6 +282,26 @@ class NativeNesCore implements NesCore {
 		this.nes.markSramSaved();
 	}
 
+	saveState(): Uint8Array {
+		return this.nes.saveState();
+	}
+
+	loadState(state: Uint8Array): void {
+		this.nes.loadState(state);
+	}
+
+	setRewind(budgetBytes?: number, frameInterval?: number): void {
+		this.nes.setRewind(budgetBytes, frameInterval);
+	}
+
+	rewind(frames: number): number {
+		return this.nes.rewind(frames);
+	}
+
+	getFrameRate(): number {
+		return this.nes.getFrameRate();
+	}
+
 	getAudioWarning(): string | null {
 		return this.audioWarning;
 	}
@@ -192,13 +310,60 @@ class NativeNesCore implements NesCore {
 		return this.nes.getDebugState();
 	}
 
+	addCheat(code: string): number {
+		return this.nes.addCheat(code);
+	}
+
+	removeCheat(id: number): boolean {
+		return this.nes.removeCheat(id);
+	}
+
+	setCheatEnabled(id: number, enabled: boolean): boolean {
+		return this.nes.setCheatEnabled(id, enabled);
+	}
+
+	listCheats(): NesCheat[] {
+		return this.nes.listCheats();
+	}
+
+	setPalette(palette: NesPalette): void {
+		if (palette instanceof Uint8Array) {
+			this.nes.setPalette(palette);
+		} else if (palette === "default") {
+			this.nes.resetPalette();
+		} else {
+			this.nes.generatePalette({ preset: NATIVE_PALETTE_PRESET_MAP[palette] });
+		}
+	}
+
+	setScaler(scaler: NesScaler, factor?: number): void {
+		this.nes.setScaler(NATIVE_SCALER_MAP[scaler], factor);
+		// The native view changes with the output size
+		this.frameBuffer = this.readFrameBuffer();
+	}
+
+	setOverscan(overscan: NesOverscan, aspectCorrection: boolean): void {
+		this.nes.setOverscan({ ...overscan, aspectCorrection });
+		this.frameBuffer = this.readFrameBuffer();
+	}
+
+	setSharedFramebuffer(name: string | null): void {
+		this.nes.setSharedFramebuffer(name);
+	}
+
+	private readFrameBuffer(): FrameBuffer {
+		const { width, height } = this.nes.getFramebufferSize();
+		return { data: this.nes.getFramebuffer(), width, height };
+	}
+
 	dispose(): void {
-		// No explicit native teardown required; napi instance is GC-managed.
+		// The napi instance is GC-managed, but the shared segment name must be freed now.
 		this.nes.setAudioEnabled(false);
+		this.nes.setSharedFramebuffer(null);
 		this.hasSram = false;
 	}
 }
 
 export function createNesCore(options: CreateNesCoreOptions = {}): NesCore {
-	return new NativeNesCore(options.enableAudio ?? false, options.videoFilter ?? "off");
+	return new NativeNesCore(options.enableAudio ?? false, options.videoFilter ?? "off", options.region ?? "auto");
 }
@@ -8,9 +8,6 @@ import { Image } from "@earendil-works/pi-tui";
 import { allocateImageId, deleteKittyImage, getCapabilities, getCellDimensions } from "@earendil-works/pi-tui";
 import type { FrameBuffer } from "./nes-core.js";
 
-export const FRAME_WIDTH = 256;
-export const FRAME_HEIGHT = 240;
-const RAW_FRAME_BYTES = FRAME_WIDTH * FRAME_HEIGHT * 3;
 const FALLBACK_TMP_DIR = "/tmp";
 const SHM_DIR = "/dev/shm";
 const IMAGE_HEIGHT_RATIO = 0.9;
@@ -18,16 +15,27 @@ const IMAGE_HEIGHT_RATIO = 0.9;
 // Renderer state + native addon loading.
 const require = createRequire(import.meta.url);
 
-interface SharedMemoryHandle {
-	name: string;
-	size: number;
-	buffer: Uint8Array;
+interface KittyPlacementOptions {
+	imageId?: number;
+	placementId?: number;
+	columns?: number;
+	rows?: number;
+	zIndex?: number;
 }
 
 interface KittyShmModule {
 	isAvailable: boolean;
 	loadError: unknown | null;
-	createSharedMemory: (size: number) => SharedMemoryHandle;
+	encodeKittyImage: (
+		frame: Uint8Array,
+		options: {
+			transport: "s" | "f" | "d";
+			width: number;
+			height: number;
+			placement?: KittyPlacementOptions;
+		},
+	) => { sequence: string; name?: string };
+	encodeKittyDelete: (imageId?: number, placementId?: number, free?: boolean) => string;
 	closeSharedMemory: (name: string) => boolean;
 }
 
@@ -39,7 +47,8 @@ function getKittyShmModule(): KittyShmModule | null {
 	}
 	try {
 		const loaded = require("./native/kitty-shm/index.js") as KittyShmModule;
-		kittyShmModule = loaded?.isAvailable ? loaded : null;
+		// Builds from before the native encoder fall back to the file transport
+		kittyShmModule = loaded?.isAvailable && typeof loaded.encodeKittyImage === "function" ? loaded : null;
 	} catch {
 		kittyShmModule = null;
 	}
@@ -50,13 +59,13 @@ export class NesImageRenderer {
 
 	private readonly imageId = allocateImageId();
 	private cachedImage?: { base64: string; width: number; height: number };
-	private cachedRaw?: { sequence: string; columns: number; rows: number };
-	private readonly rawBuffer = Buffer.alloc(RAW_FRAME_BYTES);
+	private cachedRaw?: { sequence: string; columns: number; rows: number; width: number; height: number };
+	private rawBuffer = Buffer.alloc(0);
 	private readonly rawFileDir = resolveRawDir();
 	private readonly rawFilePath = path.join(this.rawFileDir, `pi-nes-tty-graphics-${this.imageId}.raw`);
 	private readonly rawFilePathBase64 = Buffer.from(this.rawFilePath).toString("base64");
 	private rawFileFd: number | null = null;
-	private readonly sharedMemoryQueue: SharedMemoryHandle[] = [];
+	private readonly sharedMemoryQueue: string[] = [];
 	private sharedMemoryDisabled = false;
 	private sharedMemoryModule: KittyShmModule | null = null;
 	private lastFrameHash = 0;
@@ -91,7 +100,10 @@ export class NesImageRenderer {
 
 	dispose(tui: TUI): void {
 		if (getCapabilities().images === "kitty") {
-			tui.terminal.write(deleteKittyImage(this.imageId));
+			const module = this.sharedMemoryModule;
+			tui.terminal.write(
+				module ? module.encodeKittyDelete(this.imageId, undefined, true) : deleteKittyImage(this.imageId),
+			);
 		}
 		this.cachedImage = undefined;
 		this.cachedRaw = undefined;
@@ -99,9 +111,9 @@ export class NesImageRenderer {
 		this.lastLines = [];
 		this.renderErrors.clear();
 		if (this.sharedMemoryQueue.length > 0 && this.sharedMemoryModule) {
-			for (const handle of this.sharedMemoryQueue) {
+			for (const name of this.sharedMemoryQueue) {
 				try {
-					this.sharedMemoryModule.closeSharedMemory(handle.name);
+					this.sharedMemoryModule.closeSharedMemory(name);
 				} catch {
 					// ignore
 				}
@@ -137,25 +149,13 @@ export class NesImageRenderer {
 		if (!module) {
 			return null;
 		}
-		const shared = this.createSharedMemoryFrame(module);
-		if (!shared) {
-			return null;
-		}
-
-		const layout = computeKittyLayout(tui, widthCells, footerRows, pixelScale);
+		const layout = computeKittyLayout(tui, widthCells, footerRows, pixelScale, frameBuffer);
 		const { availableRows, columns, rows, padLeft } = layout;
 
-		this.fillRawBufferTarget(frameBuffer, shared.buffer);
-		const base64Name = Buffer.from(shared.name).toString("base64");
-		const sequence = encodeKittyRawSharedMemory(base64Name, {
-			widthPx: FRAME_WIDTH,
-			heightPx: FRAME_HEIGHT,
-			dataSize: RAW_FRAME_BYTES,
-			columns,
-			rows,
-			imageId: this.imageId,
-			zIndex: 0,
-		});
+		const sequence = this.encodeSharedMemoryFrame(module, frameBuffer, { columns, rows });
+		if (!sequence) {
+			return null;
+		}
 
 		const lines: string[] = [];
 		for (let i = 0; i < rows - 1; i += 1) {
@@ -178,8 +178,9 @@ export class NesImageRenderer {
 		pixelScale: number,
 		padToHeight: boolean,
 	): string[] {
-		const layout = computeKittyLayout(tui, widthCells, footerRows, pixelScale);
+		const layout = computeKittyLayout(tui, widthCells, footerRows, pixelScale, frameBuffer);
 		const { availableRows, columns, rows, padLeft } = layout;
+		const { width, height } = frameBuffer;
 
 		try {
 			this.fillRawBuffer(frameBuffer);
@@ -190,12 +191,18 @@ export class NesImageRenderer {
 		}
 
 		let cached = this.cachedRaw;
-		if (!cached || cached.columns !== columns || cached.rows !== rows) {
+		if (
+			!cached ||
+			cached.columns !== columns ||
+			cached.rows !== rows ||
+			cached.width !== width ||
+			cached.height !== height
+		) {
 			cached = {
 				sequence: encodeKittyRawFile(this.rawFilePathBase64, {
-					widthPx: FRAME_WIDTH,
-					heightPx: FRAME_HEIGHT,
-					dataSize: RAW_FRAME_BYTES,
+					widthPx: width,
+					heightPx: height,
+					dataSize: getFrameBytes(frameBuffer),
 					columns,
 					rows,
 					imageId: this.imageId,
@@ -203,6 +210,8 @@ export class NesImageRenderer {
 				}),
 				columns,
 				rows,
+				width,
+				height,
 			};
 			this.cachedRaw = cached;
 		}
@@ -228,7 +237,7 @@ export class NesImageRenderer {
 		pixelScale: number,
 		padToHeight: boolean,
 	): string[] {
-		const layout = computePngLayout(tui, widthCells, footerRows, pixelScale);
+		const layout = computePngLayout(tui, widthCells, footerRows, pixelScale, frameBuffer);
 		const { availableRows, maxWidthCells, padLeft, targetHeight, targetWidth } = layout;
 
 		const hash = hashFrame(frameBuffer, targetWidth, targetHeight);
@@ -236,10 +245,10 @@ export class NesImageRenderer {
 			try {
 				const png = new PNG({ width: targetWidth, height: targetHeight });
 				for (let y = 0; y < targetHeight; y += 1) {
-					const srcY = Math.floor((y / targetHeight) * FRAME_HEIGHT);
+					const srcY = Math.floor((y / targetHeight) * frameBuffer.height);
 					for (let x = 0; x < targetWidth; x += 1) {
-						const srcX = Math.floor((x / targetWidth) * FRAME_WIDTH);
-						const [r, g, b] = readRgb(frameBuffer, srcY * FRAME_WIDTH + srcX);
+						const srcX = Math.floor((x / targetWidth) * frameBuffer.width);
+						const [r, g, b] = readRgb(frameBuffer, srcY * frameBuffer.width + srcX);
 						const idx = (y * targetWidth + x) * 4;
 						png.data[idx] = r;
 						png.data[idx + 1] = g;
@@ -281,17 +290,21 @@ export class NesImageRenderer {
 	}
 
 	private fillRawBuffer(frameBuffer: FrameBuffer): void {
-		this.fillRawBufferTarget(frameBuffer, this.rawBuffer);
+		const frameBytes = getFrameBytes(frameBuffer);
+		if (this.rawBuffer.length !== frameBytes) {
+			this.rawBuffer = Buffer.alloc(frameBytes);
+		}
+		this.fillRawBufferTarget(frameBuffer, this.rawBuffer, frameBytes);
 	}
 
-	private fillRawBufferTarget(frameBuffer: FrameBuffer, target: Uint8Array): void {
+	private fillRawBufferTarget(frameBuffer: FrameBuffer, target: Uint8Array, frameBytes: number): void {
 		const source = frameBuffer.data;
-		if (source.length >= RAW_FRAME_BYTES) {
-			target.set(source.subarray(0, RAW_FRAME_BYTES));
+		if (source.length >= frameBytes) {
+			target.set(source.subarray(0, frameBytes));
 			return;
 		}
 		target.set(source);
-		target.fill(0, source.length, RAW_FRAME_BYTES);
+		target.fill(0, source.length, frameBytes);
 	}
 
 	private getSharedMemoryModule(): KittyShmModule | null {
@@ -310,25 +323,35 @@ export class NesImageRenderer {
 		return module;
 	}
 
-	private createSharedMemoryFrame(module: KittyShmModule): SharedMemoryHandle | null {
+	// The addon writes the frame to a new segment and builds the whole sequence
+	private encodeSharedMemoryFrame(
+		module: KittyShmModule,
+		frameBuffer: FrameBuffer,
+		size: { columns: number; rows: number },
+	): string | null {
 		try {
-			const handle = module.createSharedMemory(RAW_FRAME_BYTES);
-			if (!handle?.buffer || handle.buffer.byteLength < RAW_FRAME_BYTES) {
+			const { sequence, name } = module.encodeKittyImage(frameBuffer.data, {
+				transport: "s",
+				width: frameBuffer.width,
+				height: frameBuffer.height,
+				placement: { ...size, imageId: this.imageId, placementId: 1, zIndex: 0 },
+			});
+			if (!name) {
 				this.sharedMemoryDisabled = true;
 				return null;
 			}
-			this.sharedMemoryQueue.push(handle);
+			this.sharedMemoryQueue.push(name);
 			if (this.sharedMemoryQueue.length > 2) {
 				const stale = this.sharedMemoryQueue.shift();
 				if (stale) {
 					try {
-						module.closeSharedMemory(stale.name);
+						module.closeSharedMemory(stale);
 					} catch {
 						// ignore
 					}
 				}
 			}
-			return handle;
+			return sequence;
 		} catch {
 			this.sharedMemoryDisabled = true;
 			return null;
@@ -349,10 +372,10 @@ export class NesImageRenderer {
 	}
 }
 
-// Kitty graphics protocol (ESC_G ... ESC\\).
-// t=f (file) / t=s (shared memory), f=24 (RGB), p=1 (no scaling), q=2 (quiet), z=layer.
+// Kitty graphics protocol (ESC_G ... ESC\\) for the file fallback, the addon encodes the rest.
+// t=f (file), f=24 (RGB), p=1 (placement id), q=2 (quiet), z=layer.
 function buildKittyParams(
-	transport: "f" | "s",
+	transport: "f",
 	options: {
 		widthPx: number;
 		heightPx: number;
@@ -396,22 +419,6 @@ function encodeKittyRawFile(
 	return `\x1b_G${params.join(",")};${base64Path}\x1b\\`;
 }
 
-function encodeKittyRawSharedMemory(
-	base64Name: string,
-	options: {
-		widthPx: number;
-		heightPx: number;
-		dataSize: number;
-		columns?: number;
-		rows?: number;
-		imageId?: number;
-		zIndex?: number;
-	},
-): string {
-	const params = buildKittyParams("s", options);
-	return `\x1b_G${params.join(",")};${base64Name}\x1b\\`;
-}
-
 // Temp file/SHM selection.
 function resolveRawDir(): string {
 	const candidates = [process.env.TMPDIR, SHM_DIR, FALLBACK_TMP_DIR, os.tmpdir()].filter(
@@ -441,12 +448,16 @@ function getMaxImageRows(tui: TUI, footerRows: number): number {
 	return Math.max(1, Math.floor(availableRows * IMAGE_HEIGHT_RATIO));
 }
 
-function computeLayoutBase(tui: TUI, widthCells: number, footerRows: number) {
+function getFrameBytes(frameBuffer: FrameBuffer): number {
+	return frameBuffer.width * frameBuffer.height * 3;
+}
+
+function computeLayoutBase(tui: TUI, widthCells: number, footerRows: number, frame: FrameBuffer) {
 	const availableRows = getAvailableRows(tui, footerRows);
 	const maxRows = getMaxImageRows(tui, footerRows);
 	const cell = getCellDimensions();
 	const maxWidthByRows = Math.floor(
-		(maxRows * cell.heightPx * FRAME_WIDTH) / (FRAME_HEIGHT * cell.widthPx),
+		(maxRows * cell.heightPx * frame.width) / (frame.height * cell.widthPx),
 	);
 	const maxWidthCells = Math.max(1, Math.min(widthCells, maxWidthByRows));
 	const maxWidthPx = Math.max(1, maxWidthCells * cell.widthPx);
@@ -454,22 +465,34 @@ function computeLayoutBase(tui: TUI, widthCells: number, footerRows: number) {
 	return { availableRows, maxRows, cell, maxWidthCells, maxWidthPx, maxHeightPx };
 }
 
-function computeKittyLayout(tui: TUI, widthCells: number, footerRows: number, pixelScale: number) {
-	const base = computeLayoutBase(tui, widthCells, footerRows);
-	const maxScale = Math.min(base.maxWidthPx / FRAME_WIDTH, base.maxHeightPx / FRAME_HEIGHT);
+function computeKittyLayout(
+	tui: TUI,
+	widthCells: number,
+	footerRows: number,
+	pixelScale: number,
+	frame: FrameBuffer,
+) {
+	const base = computeLayoutBase(tui, widthCells, footerRows, frame);
+	const maxScale = Math.min(base.maxWidthPx / frame.width, base.maxHeightPx / frame.height);
 	const requestedScale = Math.max(0.5, pixelScale) * maxScale;
 	const scale = Math.min(maxScale, requestedScale);
-	const columns = Math.max(1, Math.min(base.maxWidthCells, Math.floor((FRAME_WIDTH * scale) / base.cell.widthPx)));
-	const rows = Math.max(1, Math.min(base.maxRows, Math.floor((FRAME_HEIGHT * scale) / base.cell.heightPx)));
+	const columns = Math.max(1, Math.min(base.maxWidthCells, Math.floor((frame.width * scale) / base.cell.widthPx)));
+	const rows = Math.max(1, Math.min(base.maxRows, Math.floor((frame.height * scale) / base.cell.heightPx)));
 	const padLeft = getHorizontalPadding(widthCells, columns);
 	return { ...base, columns, rows, padLeft };
 }
 
-function computePngLayout(tui: TUI, widthCells: number, footerRows: number, pixelScale: number) {
-	const base = computeLayoutBase(tui, widthCells, footerRows);
-	const scale = Math.min(base.maxWidthPx / FRAME_WIDTH, base.maxHeightPx / FRAME_HEIGHT) * pixelScale;
-	const targetWidth = Math.max(1, Math.floor(FRAME_WIDTH * scale));
-	const targetHeight = Math.max(1, Math.floor(FRAME_HEIGHT * scale));
+function computePngLayout(
+	tui: TUI,
+	widthCells: number,
+	footerRows: number,
+	pixelScale: number,
+	frame: FrameBuffer,
+) {
+	const base = computeLayoutBase(tui, widthCells, footerRows, frame);
+	const scale = Math.min(base.maxWidthPx / frame.width, base.maxHeightPx / frame.height) * pixelScale;
+	const targetWidth = Math.max(1, Math.floor(frame.width * scale));
+	const targetHeight = Math.max(1, Math.floor(frame.height * scale));
 	const columns = Math.max(1, Math.min(base.maxWidthCells, Math.floor(targetWidth / base.cell.widthPx)));
 	const padLeft = getHorizontalPadding(widthCells, columns);
 	return { ...base, targetWidth, targetHeight, padLeft };
@@ -518,12 +541,12 @@ function readRgb(frameBuffer: FrameBuffer, index: number): [number, number, numb
 
 function hashFrame(frameBuffer: FrameBuffer, width: number, height: number): number {
 	let hash = width ^ (height << 16);
-	const stepX = Math.max(1, Math.floor(FRAME_WIDTH / 64));
-	const stepY = Math.max(1, Math.floor(FRAME_HEIGHT / 64));
+	const stepX = Math.max(1, Math.floor(frameBuffer.width / 64));
+	const stepY = Math.max(1, Math.floor(frameBuffer.height / 64));
 	const data = frameBuffer.data;
-	for (let y = 0; y < FRAME_HEIGHT; y += stepY) {
-		const rowOffset = y * FRAME_WIDTH;
-		for (let x = 0; x < FRAME_WIDTH; x += stepX) {
+	for (let y = 0; y < frameBuffer.height; y += stepY) {
+		const rowOffset = y * frameBuffer.width;
+		for (let x = 0; x < frameBuffer.width; x += stepX) {
 			const base = (rowOffset + x) * 3;
 			const r = data[base] ?? 0;
 			const g = data[base + 1] ?? 0;
@@ -10,6 +10,7 @@ describe("config", () => {
 			assert.strictEqual(config.renderer, DEFAULT_CONFIG.renderer);
 			assert.strictEqual(config.imageQuality, DEFAULT_CONFIG.imageQuality);
 			assert.strictEqual(config.videoFilter, DEFAULT_CONFIG.videoFilter);
+			assert.strictEqual(config.region, DEFAULT_CONFIG.region);
 			assert.strictEqual(config.pixelScale, DEFAULT_CONFIG.pixelScale);
 		});
 
@@ -53,6 +54,49 @@ describe("config", () => {
 			assert.strictEqual(config.videoFilter, DEFAULT_CONFIG.videoFilter);
 		});
 
+		test("accepts valid region", () => {
+			assert.strictEqual(normalizeConfig({ region: "pal" }).region, "pal");
+			assert.strictEqual(normalizeConfig({ region: "dendy" }).region, "dendy");
+		});
+
+		test("defaults invalid region to auto", () => {
+			assert.strictEqual(normalizeConfig({ region: "secam" }).region, "auto");
+		});
+
+		test("accepts palette presets and resolves .pal paths", () => {
+			assert.strictEqual(normalizeConfig({}).palette, "default");
+			assert.strictEqual(normalizeConfig({ palette: "nestopia" }).palette, "nestopia");
+			assert.strictEqual(normalizeConfig({ palette: "/palettes/smooth.pal" }).palette, "/palettes/smooth.pal");
+			assert.strictEqual(normalizeConfig({ palette: " " }).palette, "default");
+			assert.strictEqual(normalizeConfig({ palette: 3 }).palette, "default");
+		});
+
+		test("normalizes upscaler and factor", () => {
+			assert.strictEqual(normalizeConfig({}).upscaler, "off");
+			assert.strictEqual(normalizeConfig({ upscaler: "hq3x" }).upscaler, "hq3x");
+			assert.strictEqual(normalizeConfig({ upscaler: "hq4x" }).upscaler, "off");
+			assert.strictEqual(normalizeConfig({}).upscaleFactor, 2);
+			assert.strictEqual(normalizeConfig({ upscaleFactor: 10 }).upscaleFactor, 6);
+			assert.strictEqual(normalizeConfig({ upscaleFactor: 2.6 }).upscaleFactor, 3);
+		});
+
+		test("normalizes overscan sides", () => {
+			assert.deepStrictEqual(normalizeConfig({}).overscan, { top: 0, bottom: 0, left: 0, right: 0 });
+			assert.deepStrictEqual(normalizeConfig({ overscan: { top: 8, bottom: 100, left: -2, right: "8" } }).overscan, {
+				top: 8,
+				bottom: 64,
+				left: 0,
+				right: 0,
+			});
+			assert.strictEqual(normalizeConfig({ aspectCorrection: true }).aspectCorrection, true);
+		});
+
+		test("trims the shared framebuffer name", () => {
+			assert.strictEqual(normalizeConfig({}).sharedFramebuffer, "");
+			assert.strictEqual(normalizeConfig({ sharedFramebuffer: " pi-nes " }).sharedFramebuffer, "pi-nes");
+			assert.strictEqual(normalizeConfig({ sharedFramebuffer: 1 }).sharedFramebuffer, "");
+		});
+
 		test("clamps pixelScale to valid range", () => {
 			assert.strictEqual(normalizeConfig({ pixelScale: 0.1 }).pixelScale, 0.5);
 			assert.strictEqual(normalizeConfig({ pixelScale: 10 }).pixelScale, 4);